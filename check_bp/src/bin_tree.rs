@@ -1,3 +1,4 @@
+use crate::check_graph::CheckGraphError;
 use pyo3::prelude::*;
 
 pub struct BinMultTree<T: Clone> {
@@ -20,8 +21,9 @@ pub fn pointwise_mult(op0: &Vec<f64>, op1: &Vec<f64>) -> Vec<f64> {
         })
         .map(|p| p.abs())
         .unwrap_or(f64::NAN);
+    //Degenerate products cannot be normalized and are left as they are
     if max.is_nan() || max == 0.0 {
-        panic!("Could not normalize.");
+        return res;
     }
     res.iter_mut().for_each(|p| *p /= max);
     res
@@ -29,7 +31,6 @@ pub fn pointwise_mult(op0: &Vec<f64>, op1: &Vec<f64>) -> Vec<f64> {
 
 impl<T: Clone> BinMultTree<T> {
     pub fn new(leaves: Vec<T>, mult: fn(&T, &T) -> T) -> Self {
-        assert_eq!(leaves.len() % 2, 0);
         assert!(leaves.len() >= 2);
         let downward_tree = vec![leaves];
         let upward_tree = None;
@@ -143,6 +144,22 @@ impl<T: Clone> BinMultTree<T> {
     }
 }
 
+fn check_leaves(count: usize) -> Result<(), CheckGraphError> {
+    if count < 2 {
+        return Err(CheckGraphError::dimension(format!(
+            "A tree needs at least two leaves but got {}.",
+            count
+        )));
+    }
+    Ok(())
+}
+
+//A tree can only be calculated once
+fn take_tree<T: Clone>(tree: &mut Option<BinMultTree<T>>) -> Result<BinMultTree<T>, CheckGraphError> {
+    std::mem::replace(tree, None)
+        .ok_or(CheckGraphError::new("Tree already calculated.".to_owned()))
+}
+
 #[pyclass]
 pub struct PyBinMultTreeList {
     tree: Option<BinMultTree<Vec<f64>>>,
@@ -151,14 +168,15 @@ pub struct PyBinMultTreeList {
 #[pymethods]
 impl PyBinMultTreeList {
     #[new]
-    pub fn new(leaves: Vec<Vec<f64>>) -> Self {
-        Self {
+    pub fn new(leaves: Vec<Vec<f64>>) -> PyResult<Self> {
+        check_leaves(leaves.len())?;
+        Ok(Self {
             tree: Some(BinMultTree::new(leaves, pointwise_mult)),
-        }
+        })
     }
-    pub fn calculate(&mut self) -> Vec<Vec<f64>> {
-        let t = std::mem::replace(&mut self.tree, None).expect("Tree not valid.");
-        t.calculate()
+    pub fn calculate(&mut self) -> PyResult<Vec<Vec<f64>>> {
+        let t = take_tree(&mut self.tree)?;
+        Ok(t.calculate())
     }
 }
 
@@ -174,13 +192,14 @@ pub struct PyBinMultTreeInt {
 #[pymethods]
 impl PyBinMultTreeInt {
     #[new]
-    pub fn new(leaves: Vec<i32>) -> Self {
-        Self {
+    pub fn new(leaves: Vec<i32>) -> PyResult<Self> {
+        check_leaves(leaves.len())?;
+        Ok(Self {
             tree: Some(BinMultTree::new(leaves, mult_int)),
-        }
+        })
     }
-    pub fn calculate(&mut self) -> Vec<i32> {
-        let t = std::mem::replace(&mut self.tree, None).expect("Tree not valid.");
-        t.calculate()
+    pub fn calculate(&mut self) -> PyResult<Vec<i32>> {
+        let t = take_tree(&mut self.tree)?;
+        Ok(t.calculate())
     }
 }
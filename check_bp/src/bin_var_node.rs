@@ -1,15 +1,38 @@
 use crate::bin_tree::BinMultTree;
-use crate::check_msg::CheckMsg;
+use crate::check_msg::{CheckMessage, DEGENERATE_DISTRIBUTION};
 use belief_propagation::{BPError, BPResult, Msg, NodeFunction, NodeIndex};
 
 pub enum CtrlMsgA {
     Fixed(bool),
+    Messages(Vec<(NodeIndex, Vec<f64>)>),
+    //Probabilities of -eta..=eta, not normalized
+    Belief(Vec<f64>),
     None,
 }
 
+//Messages are given as (other node, CheckMessage::raw)
 pub enum CtrlMsg {
     SetFixed(i16),
     GetFixed,
+    //Releases a fixed value, the node continues from its inbox
+    ClearFixed,
+    //Likelihoods of -eta..=eta multiplied into the prior, None removes them
+    SetEvidence(Option<Vec<f64>>),
+    //Replaces the prior (probabilities of -eta..=eta)
+    SetPrior(Vec<f64>),
+    //Prior, evidence and the messages received last (or the fixed value)
+    GetBelief,
+    //Last messages sent by a check node
+    GetMessages,
+    //Check nodes: messages sent last, variable nodes: messages received last,
+    //the first step then continues from them instead of sending the prior
+    SetMessages(Vec<(NodeIndex, Vec<f64>)>),
+}
+
+pub fn messages_from_raw<M: CheckMessage>(msgs: Vec<(NodeIndex, Vec<f64>)>) -> BPResult<Vec<(NodeIndex, M)>> {
+    msgs.into_iter()
+        .map(|(node, raw)| M::from_raw(&raw).map(|msg| (node, msg)))
+        .collect()
 }
 
 impl std::default::Default for CtrlMsgA {
@@ -18,31 +41,47 @@ impl std::default::Default for CtrlMsgA {
     }
 }
 
-fn mult_msgs<const ETA: usize>(op0: &CheckMsg<ETA>, op1: &CheckMsg<ETA>) -> CheckMsg<ETA> {
-    let mut data = [0 as f64; ETA];
-    for (i, (p0, p1)) in op0.data.iter().zip(op1.data.iter()).enumerate() {
-        data[i] = p0 * p1;
+fn with_evidence<M: CheckMessage>(prior: &M, evidence: &M) -> BPResult<M> {
+    let mut res = prior.product(evidence);
+    if res.is_degenerate() {
+        return Err(BPError::new(
+            "BinVariableNode::prior".to_owned(),
+            format!("{}: evidence contradicts the prior", DEGENERATE_DISTRIBUTION),
+        )
+        .attach_debug_object("evidence", evidence));
     }
-    let mut res = CheckMsg::from_data(data);
-    res.normalize().expect("Failed to normalize.");
+    res.normalize()?;
+    Ok(res)
+}
+
+fn mult_msgs<M: CheckMessage>(op0: &M, op1: &M) -> M {
+    let mut res = op0.product(op1);
+    //Products that are zero everywhere are caught in node_function
+    let _ = res.normalize();
     res
 }
 
 #[derive(Clone)]
-pub struct BinVariableNode<const ETA: usize> {
+pub struct BinVariableNode<M: CheckMessage> {
     connections: Option<Vec<NodeIndex>>,
-    prior: Option<CheckMsg<ETA>>,
+    prior: Option<M>,
     has_propagated: bool,
     is_fixed: Option<i16>,
+    restored: Option<Vec<(NodeIndex, M)>>,
+    evidence: Option<M>,
+    received: Vec<M>,
 }
 
-impl<const ETA: usize> BinVariableNode<ETA> {
+impl<M: CheckMessage> BinVariableNode<M> {
     pub fn new() -> Self {
         BinVariableNode {
             prior: None,
             connections: None,
             has_propagated: false,
             is_fixed: None,
+            restored: None,
+            evidence: None,
+            received: Vec::new(),
         }
     }
 
@@ -50,7 +89,43 @@ impl<const ETA: usize> BinVariableNode<ETA> {
         self.is_fixed = value;
     }
 
-    pub fn set_prior(&mut self, prior: &CheckMsg<ETA>) -> BPResult<()> {
+    fn connections(&self) -> BPResult<&Vec<NodeIndex>> {
+        self.connections.as_ref().ok_or(BPError::new(
+            "BinVariableNode::connections".to_owned(),
+            "Did not find connections. Node not initialized?".to_owned(),
+        ))
+    }
+
+    //Prior times evidence
+    fn prior(&self) -> BPResult<M> {
+        let prior = self.prior.ok_or(BPError::new(
+            "BinVariableNode::prior".to_owned(),
+            "No prior set.".to_owned(),
+        ))?;
+        match self.evidence {
+            Some(evidence) => with_evidence(&prior, &evidence),
+            None => Ok(prior),
+        }
+    }
+
+    fn belief(&self) -> BPResult<M> {
+        if let Some(v) = self.is_fixed {
+            return Ok(M::fixed(v));
+        }
+        let mut belief = self.prior()?;
+        for msg in self.received.iter() {
+            belief = mult_msgs(&belief, msg);
+        }
+        if belief.is_degenerate() {
+            return Err(BPError::new(
+                "BinVariableNode::belief".to_owned(),
+                format!("{}: contradicting messages in inbox", DEGENERATE_DISTRIBUTION),
+            ));
+        }
+        Ok(belief)
+    }
+
+    pub fn set_prior(&mut self, prior: &M) -> BPResult<()> {
         if self.prior.is_some() {
             return Err(BPError::new(
                 "BinVariableNode::set_prior".to_owned(),
@@ -63,11 +138,9 @@ impl<const ETA: usize> BinVariableNode<ETA> {
     }
 }
 
-impl<const ETA: usize> NodeFunction<i16, CheckMsg<ETA>, CtrlMsg, CtrlMsgA>
-    for BinVariableNode<ETA>
-{
-    fn get_prior(&self) -> Option<CheckMsg<ETA>> {
-        self.prior.clone()
+impl<M: CheckMessage> NodeFunction<i16, M, CtrlMsg, CtrlMsgA> for BinVariableNode<M> {
+    fn get_prior(&self) -> Option<M> {
+        self.prior().ok()
     }
     fn initialize(&mut self, connections: Vec<NodeIndex>) -> BPResult<()> {
         if self.prior.is_none() {
@@ -81,28 +154,32 @@ impl<const ETA: usize> NodeFunction<i16, CheckMsg<ETA>, CtrlMsg, CtrlMsgA>
     }
     fn node_function(
         &mut self,
-        inbox: Vec<(NodeIndex, CheckMsg<ETA>)>,
-    ) -> BPResult<Vec<(NodeIndex, CheckMsg<ETA>)>> {
+        mut inbox: Vec<(NodeIndex, M)>,
+    ) -> BPResult<Vec<(NodeIndex, M)>> {
         if let Some(v) = self.is_fixed {
-            let mut msg = CheckMsg::new();
-            msg.set_fixed_value(v);
+            self.received = inbox.iter().map(|(_, msg)| *msg).collect();
+            let msg = M::fixed(v);
             return Ok(self
-                .connections
-                .as_ref()
-                .expect("Did not find connections.")
+                .connections()?
                 .iter()
                 .map(|con| (*con, msg))
                 .collect());
         }
         if !self.has_propagated {
+            match self.restored.take() {
+                Some(restored) => inbox = restored,
+                None => {
+                    let prior = self.prior()?;
+                    let msgs = self
+                        .connections()?
+                        .iter()
+                        .map(|con| (*con, prior))
+                        .collect();
+                    self.has_propagated = true;
+                    return Ok(msgs);
+                }
+            }
             self.has_propagated = true;
-            return Ok(self
-                .connections
-                .as_ref()
-                .expect("Did not find connections. Node not initialized?")
-                .iter()
-                .map(|con| (*con, self.prior.expect("No prior set").clone()))
-                .collect());
         }
         let mut leafs = Vec::with_capacity(inbox.len());
         let mut indices = Vec::with_capacity(inbox.len());
@@ -118,8 +195,14 @@ impl<const ETA: usize> NodeFunction<i16, CheckMsg<ETA>, CtrlMsg, CtrlMsgA>
             leafs.push(msg);
             indices.push(index);
         }
-        let prods = BinMultTree::new(leafs, mult_msgs)
-            .calculate_with_prior(&self.prior.expect("No prior set."));
+        self.received = leafs.clone();
+        let prior = self.prior()?;
+        //Sparse equations can leave variables with less than two connections
+        let prods = match leafs.len() {
+            0 => Vec::new(),
+            1 => vec![prior],
+            _ => BinMultTree::new(leafs, mult_msgs).calculate_with_prior(&prior),
+        };
         for msg in &prods {
             if !msg.is_valid() {
                 return Err(BPError::new(
@@ -128,6 +211,13 @@ impl<const ETA: usize> NodeFunction<i16, CheckMsg<ETA>, CtrlMsg, CtrlMsgA>
                 )
                 .attach_debug_object("msg (the invalid message)", msg));
             }
+            if msg.is_degenerate() {
+                return Err(BPError::new(
+                    "BinVariableNode::node_function".to_owned(),
+                    format!("{}: contradicting messages in inbox", DEGENERATE_DISTRIBUTION),
+                )
+                .attach_debug_object("msg (the product)", msg));
+            }
         }
         Ok(indices.into_iter().zip(prods.into_iter()).collect())
     }
@@ -146,15 +236,57 @@ impl<const ETA: usize> NodeFunction<i16, CheckMsg<ETA>, CtrlMsg, CtrlMsgA>
         Ok(match ctrl_msg {
             CtrlMsg::GetFixed => CtrlMsgA::Fixed(self.is_fixed.is_some()),
             CtrlMsg::SetFixed(v) => {
-                assert!(v >= -(ETA as i16) / 2 && v <= ETA as i16 / 2);
+                if v < -(M::SIZE as i16) / 2 || v > M::SIZE as i16 / 2 {
+                    return Err(BPError::new(
+                        "BinVariableNode::send_control_message".to_owned(),
+                        format!("Value {} out of range.", v),
+                    ));
+                }
                 self.set_fixed(Some(v));
                 CtrlMsgA::default()
             }
+            CtrlMsg::ClearFixed => {
+                self.set_fixed(None);
+                CtrlMsgA::default()
+            }
+            CtrlMsg::SetEvidence(likelihoods) => {
+                let evidence = likelihoods.map(|l| M::from_probabilities(&l));
+                if let (Some(prior), Some(evidence)) = (self.prior, evidence) {
+                    with_evidence(&prior, &evidence)?;
+                }
+                self.evidence = evidence;
+                CtrlMsgA::default()
+            }
+            CtrlMsg::SetPrior(probs) => {
+                let prior = M::from_probabilities(&probs);
+                if let Some(evidence) = self.evidence {
+                    with_evidence(&prior, &evidence)?;
+                } else if prior.is_degenerate() {
+                    return Err(BPError::new(
+                        "BinVariableNode::send_control_message".to_owned(),
+                        format!("{}: prior is zero everywhere", DEGENERATE_DISTRIBUTION),
+                    ));
+                }
+                self.prior = Some(prior);
+                CtrlMsgA::default()
+            }
+            CtrlMsg::GetBelief => CtrlMsgA::Belief(self.belief()?.probabilities()),
+            CtrlMsg::SetMessages(msgs) => {
+                self.restored = Some(messages_from_raw(msgs)?);
+                self.has_propagated = false;
+                CtrlMsgA::default()
+            }
+            CtrlMsg::GetMessages => {
+                return Err(BPError::new(
+                    "BinVariableNode::send_control_message".to_owned(),
+                    "Variable nodes do not keep their messages.".to_owned(),
+                ))
+            }
         })
     }
     fn is_ready(
         &self,
-        recv_from: &Vec<(NodeIndex, CheckMsg<ETA>)>,
+        recv_from: &Vec<(NodeIndex, M)>,
         current_step: usize,
     ) -> BPResult<bool> {
         if let Some(connections) = &self.connections {
@@ -1,50 +1,97 @@
 use crate::bin_var_node::{BinVariableNode, CtrlMsg, CtrlMsgA};
-use crate::check_msg::CheckMsg;
-use crate::check_node::{CheckNode, CmpOperator};
-use belief_propagation::{BPError, BPGraph, Msg, BPResult, Probability};
+use crate::checkpoint::{Checkpoint, NodeDef};
+use crate::decimation::{Decimation, DecimationReport, DecimationSettings};
+use crate::check_msg::{CheckMessage, CheckMsg, DEGENERATE_DISTRIBUTION};
+use crate::log_check_msg::LogCheckMsg;
+use crate::statistics::{argmax, iteration_stats, IterationStats};
+use crate::schedule::{residual_threshold, NodeSchedule, PropagationSettings, Residual, Schedule};
+use crate::check_node::{required_fft_size, CheckNode, CmpOperator};
+use belief_propagation::{BPError, BPGraph, Msg, Probability};
 use pyo3::prelude::*;
 use pyo3::{create_exception, PyResult};
 use rustfft::{num_complex::Complex, FftPlanner};
-use std::collections::HashMap;
-use std::convert::TryInto;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
 use crossbeam;
+use numpy::{PyArray1, PyArray2, PyReadonlyArray1, PyReadonlyArray2};
 
-const N: usize = 1024;
-#[cfg(feature = "kyber1024")]
-const K: usize = 2048;
-#[cfg(feature = "kyber768")]
-const K: usize = 1536;
-#[cfg(feature = "kyber512")]
-const K: usize = 1024;
-
-#[cfg(feature = "kyber1024")]
-const ETA: usize = 5;
-#[cfg(feature = "kyber768")]
-const ETA: usize = 5;
-#[cfg(feature = "kyber512")]
-const ETA: usize = 7;
+//Largest transform size of a check node, equations needing more are rejected
+const MAX_FFT_SIZE: usize = 1 << 20;
+
+//Message sizes (2*eta+1) of the supported parameter sets
+const ETA2: usize = 5;
+const ETA3: usize = 7;
 
 create_exception!(check_bp, PyCheckGraphError, pyo3::exceptions::PyException);
+create_exception!(check_bp, PyDimensionError, PyCheckGraphError);
+create_exception!(check_bp, PyNodeNotFoundError, PyCheckGraphError);
+create_exception!(check_bp, PyDegenerateDistributionError, PyCheckGraphError);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorKind {
+    General,
+    Dimension,
+    NodeNotFound,
+    DegenerateDistribution,
+}
 
 #[derive(Debug)]
 pub struct CheckGraphError {
     desc: String,
+    kind: ErrorKind,
+    node: Option<usize>,
 }
 
 impl CheckGraphError {
     pub fn new(desc: String) -> Self {
-        CheckGraphError { desc: desc }
+        CheckGraphError {
+            desc: desc,
+            kind: ErrorKind::General,
+            node: None,
+        }
     }
-    pub fn from_bp(err: BPError) -> Self {
+    pub fn dimension(desc: String) -> Self {
         CheckGraphError {
-            desc: err.to_string(),
+            kind: ErrorKind::Dimension,
+            ..CheckGraphError::new(desc)
         }
     }
+    pub fn node_not_found(node: usize, node_count: usize) -> Self {
+        CheckGraphError {
+            kind: ErrorKind::NodeNotFound,
+            ..CheckGraphError::new(format!(
+                "Node not found (number of variable nodes is {}).",
+                node_count
+            ))
+        }
+        .with_node(node)
+    }
+    pub fn degenerate(desc: String) -> Self {
+        CheckGraphError {
+            kind: ErrorKind::DegenerateDistribution,
+            ..CheckGraphError::new(desc)
+        }
+    }
+    pub fn from_bp(err: BPError) -> Self {
+        let desc = err.to_string();
+        if desc.contains(DEGENERATE_DISTRIBUTION) {
+            CheckGraphError::degenerate(desc)
+        } else {
+            CheckGraphError::new(desc)
+        }
+    }
+    pub fn with_node(mut self, node: usize) -> Self {
+        self.node = Some(node);
+        self
+    }
 }
 
 impl std::fmt::Display for CheckGraphError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "An error occured in belief_propagation: {}", self.desc)
+        match self.node {
+            Some(node) => write!(f, "An error occured at node {}: {}", node, self.desc),
+            None => write!(f, "An error occured in belief_propagation: {}", self.desc),
+        }
     }
 }
 
@@ -56,214 +103,1357 @@ impl std::convert::From<BPError> for CheckGraphError {
     }
 }
 
+//The exception arguments are (message, node index or None)
 impl std::convert::From<CheckGraphError> for PyErr {
     fn from(err: CheckGraphError) -> PyErr {
-        PyCheckGraphError::new_err(err.to_string())
+        let args = (err.to_string(), err.node);
+        match err.kind {
+            ErrorKind::General => PyCheckGraphError::new_err(args),
+            ErrorKind::Dimension => PyDimensionError::new_err(args),
+            ErrorKind::NodeNotFound => PyNodeNotFoundError::new_err(args),
+            ErrorKind::DegenerateDistribution => PyDegenerateDistributionError::new_err(args),
+        }
     }
 }
 
+type Graph<M> = BPGraph<i16, M, CtrlMsg, CtrlMsgA>;
+
+//One graph per supported message size and representation, selected at
+//construction time
+enum GraphVariant {
+    Eta2(Graph<CheckMsg<ETA2>>),
+    Eta3(Graph<CheckMsg<ETA3>>),
+    Eta2Log(Graph<LogCheckMsg<ETA2>>),
+    Eta3Log(Graph<LogCheckMsg<ETA3>>),
+}
+
+//Runs $body with $g bound to the concretely typed graph
+macro_rules! dispatch {
+    ($graph:expr, $g:ident => $body:expr) => {
+        match $graph {
+            GraphVariant::Eta2($g) => $body,
+            GraphVariant::Eta3($g) => $body,
+            GraphVariant::Eta2Log($g) => $body,
+            GraphVariant::Eta3Log($g) => $body,
+        }
+    };
+}
+
 #[pyclass]
 pub struct CheckGraph {
-    g: BPGraph<i16, CheckMsg<ETA>, CtrlMsg, CtrlMsgA>,
+    g: GraphVariant,
+    var_count: usize,
     var_nodes: usize,
+    settings: Arc<PropagationSettings>,
+    schedule: Schedule,
+    //One per check node, in order of insertion
+    residuals: Vec<Arc<Residual>>,
+    step: usize,
+    //Records statistics after every call of propagate if set
+    #[pyo3(get, set)]
+    track_statistics: bool,
+    stats: Vec<IterationStats>,
+    last_marginals: Option<Vec<Vec<f64>>>,
+    //Definition of the graph for checkpoints
+    nodes: Vec<NodeDef>,
+    check_nodes: Vec<usize>,
+    fixed: BTreeMap<usize, i16>,
+    evidence: BTreeMap<usize, Vec<f64>>,
+    initialized: bool,
+    //Transform size of all check nodes added afterwards, derived from the
+    //coefficients of each equation if None
+    #[pyo3(get)]
+    fft_size: Option<usize>,
 }
 
 #[pymethods]
 impl CheckGraph {
+    //log_domain stores messages as log-probabilities, which is slower but
+    //does not underflow with many check nodes per variable
     #[new]
-    fn new() -> Self {
-        let mut g = BPGraph::new();
-        g.set_normalize(false);
-        Self {
+    #[args(log_domain = "false")]
+    fn new(var_count: usize, eta: usize, log_domain: bool) -> PyResult<Self> {
+        let g = match (eta, log_domain) {
+            (2, false) => GraphVariant::Eta2(new_graph()),
+            (3, false) => GraphVariant::Eta3(new_graph()),
+            (2, true) => GraphVariant::Eta2Log(new_graph()),
+            (3, true) => GraphVariant::Eta3Log(new_graph()),
+            _ => {
+                return Err(PyErr::from(CheckGraphError::new(format!(
+                    "Unsupported eta {} (supported are 2 and 3).",
+                    eta
+                ))))
+            }
+        };
+        Ok(Self {
             g,
+            var_count,
             var_nodes: 0,
+            settings: Arc::new(PropagationSettings::new()),
+            schedule: Schedule::Flooding,
+            residuals: Vec::new(),
+            step: 0,
+            track_statistics: false,
+            stats: Vec::new(),
+            last_marginals: None,
+            nodes: Vec::new(),
+            check_nodes: Vec::new(),
+            fixed: BTreeMap::new(),
+            evidence: BTreeMap::new(),
+            initialized: false,
+            fft_size: None,
+        })
+    }
+
+    #[getter]
+    fn var_count(&self) -> usize {
+        self.var_count
+    }
+
+    #[getter]
+    fn eta(&self) -> usize {
+        match self.g {
+            GraphVariant::Eta2(_) | GraphVariant::Eta2Log(_) => ETA2 / 2,
+            GraphVariant::Eta3(_) | GraphVariant::Eta3Log(_) => ETA3 / 2,
         }
     }
 
-    fn set_fixed(&mut self, node_index: usize, value: i16) {
-        self.g
+    #[getter]
+    fn log_domain(&self) -> bool {
+        match self.g {
+            GraphVariant::Eta2Log(_) | GraphVariant::Eta3Log(_) => true,
+            _ => false,
+        }
+    }
+
+    fn set_fixed(&mut self, node_index: usize, value: i16) -> PyResult<()> {
+        self.check_var_node(node_index)?;
+        let eta = self.eta() as i16;
+        if value.abs() > eta {
+            return Err(PyErr::from(
+                CheckGraphError::dimension(format!(
+                    "Value {} out of range (eta is {}).",
+                    value, eta
+                ))
+                .with_node(node_index),
+            ));
+        }
+        dispatch!(&mut self.g, g => g
             .send_control_message(node_index, CtrlMsg::SetFixed(value))
-            .expect("Node not found.");
+            .map_err(|e| CheckGraphError::from_bp(e).with_node(node_index))?);
+        self.fixed.insert(node_index, value);
+        Ok(())
     }
 
-    fn get_fixed(&mut self, node_index: usize) -> bool {
-        let response: CtrlMsgA = self
-            .g
+    fn get_fixed(&mut self, node_index: usize) -> PyResult<bool> {
+        self.check_var_node(node_index)?;
+        let response: CtrlMsgA = dispatch!(&mut self.g, g => g
             .send_control_message(node_index, CtrlMsg::GetFixed)
-            .expect("Node not found.");
+            .map_err(|e| CheckGraphError::from_bp(e).with_node(node_index))?);
         match response {
-            CtrlMsgA::Fixed(v) => v,
-            CtrlMsgA::None => panic!("Node did not return valid fixed state."),
+            CtrlMsgA::Fixed(v) => Ok(v),
+            _ => Err(PyErr::from(
+                CheckGraphError::new("Node did not return valid fixed state.".to_owned())
+                    .with_node(node_index),
+            )),
+        }
+    }
+
+    //Releases a fixed variable, it continues from the messages it received
+    fn unfix(&mut self, node_index: usize) -> PyResult<()> {
+        self.check_var_node(node_index)?;
+        Ok(self.unfix_node(node_index)?)
+    }
+
+    //Multiplies the prior with likelihoods of -eta..=eta (e.g. side-channel
+    //information), replacing evidence set before
+    fn set_evidence(&mut self, node_index: usize, likelihoods: Vec<f64>) -> PyResult<()> {
+        self.check_var_node(node_index)?;
+        check_row(&likelihoods, 2 * self.eta() + 1)?;
+        dispatch!(&mut self.g, g => g
+            .send_control_message(node_index, CtrlMsg::SetEvidence(Some(likelihoods.clone())))
+            .map_err(|e| CheckGraphError::from_bp(e).with_node(node_index))?);
+        self.evidence.insert(node_index, likelihoods);
+        Ok(())
+    }
+
+    fn clear_evidence(&mut self, node_index: usize) -> PyResult<()> {
+        self.check_var_node(node_index)?;
+        dispatch!(&mut self.g, g => g
+            .send_control_message(node_index, CtrlMsg::SetEvidence(None))
+            .map_err(|e| CheckGraphError::from_bp(e).with_node(node_index))?);
+        self.evidence.remove(&node_index);
+        Ok(())
+    }
+
+    //Replaces the prior (probabilities of -eta..=eta) of a variable
+    fn set_prior(&mut self, node_index: usize, prior: Vec<f64>) -> PyResult<()> {
+        self.check_var_node(node_index)?;
+        check_row(&prior, 2 * self.eta() + 1)?;
+        dispatch!(&mut self.g, g => g
+            .send_control_message(node_index, CtrlMsg::SetPrior(prior.clone()))
+            .map_err(|e| CheckGraphError::from_bp(e).with_node(node_index))?);
+        if let Some(NodeDef::Var { prior: def, .. }) = self.nodes.get_mut(node_index) {
+            *def = prior;
         }
+        Ok(())
+    }
+
+    //Current belief of a variable from its prior, evidence and the messages it
+    //received last, or its fixed value
+    fn get_belief(&mut self, node_index: usize) -> PyResult<HashMap<i16, f64>> {
+        self.check_var_node(node_index)?;
+        let response: CtrlMsgA = dispatch!(&mut self.g, g => g
+            .send_control_message(node_index, CtrlMsg::GetBelief)
+            .map_err(|e| CheckGraphError::from_bp(e).with_node(node_index))?);
+        let belief = match response {
+            CtrlMsgA::Belief(belief) => belief,
+            _ => {
+                return Err(PyErr::from(
+                    CheckGraphError::new("Node did not return its belief.".to_owned())
+                        .with_node(node_index),
+                ))
+            }
+        };
+        let sum: f64 = belief.iter().sum();
+        if !sum.is_finite() || sum <= 0.0 {
+            return Err(PyErr::from(
+                CheckGraphError::degenerate(format!(
+                    "{}: belief {:?} sums to {}.",
+                    DEGENERATE_DISTRIBUTION, belief, sum
+                ))
+                .with_node(node_index),
+            ));
+        }
+        let eta = self.eta() as i16;
+        Ok(belief
+            .into_iter()
+            .enumerate()
+            .map(|(i, p)| (i as i16 - eta, p / sum))
+            .collect())
     }
 
     fn set_check_validity(&mut self, value: bool) {
-        self.g.set_check_validity(value);
+        dispatch!(&mut self.g, g => g.set_check_validity(value));
     }
     fn add_var_nodes(&mut self, prior: HashMap<i16, f64>) -> PyResult<()> {
-        let mut prior_msg = CheckMsg::new(); //TODO:Ineffcient
-        for (v, p) in prior {
-            prior_msg[v] = p;
-        }
-        for i in 0..K {
-            let mut n = BinVariableNode::new();
-            prior_msg
-                .normalize()
-                .map_err(|e| CheckGraphError::from_bp(e))?;
-            n.set_prior(&prior_msg)
-                .map_err(|e| CheckGraphError::from_bp(e))?;
-            self.g.add_node(i.to_string(), Box::new(n));
-            self.var_nodes += 1;
+        self.check_no_var_nodes()?;
+        let row = row_from_map(&prior, 2 * self.eta() + 1)?;
+        for i in 0..self.var_count {
+            self.add_var_node_row(i.to_string(), row.clone())?;
         }
         Ok(())
     }
-    fn add_var_node(&mut self, name: String, prior: HashMap<i16, f64>) -> PyResult<usize> {
-        let mut prior_msg = CheckMsg::new(); //TODO:Ineffcient
-        for (v, p) in prior {
-            prior_msg[v] = p;
+    //One prior per variable, each row holds the probabilities of -eta..=eta
+    fn add_var_nodes_with_priors(&mut self, priors: Vec<Vec<f64>>) -> PyResult<()> {
+        self.check_no_var_nodes()?;
+        if priors.len() != self.var_count {
+            return Err(PyErr::from(CheckGraphError::dimension(format!(
+                "Wrong number of priors (should be {} but is {}).",
+                self.var_count,
+                priors.len()
+            ))));
         }
-        let mut n = BinVariableNode::new();
-        prior_msg
-            .normalize()
-            .map_err(|e| CheckGraphError::from_bp(e))?;
-        n.set_prior(&prior_msg)
-            .map_err(|e| CheckGraphError::from_bp(e))?;
-        let idx = self.g.add_node(name, Box::new(n));
-        self.var_nodes += 1;
-        Ok(idx)
+        self.var_nodes += dispatch!(&mut self.g, g => add_var_nodes_from_rows(g, &priors))?;
+        for (i, prior) in priors.into_iter().enumerate() {
+            self.nodes.push(NodeDef::Var {
+                name: i.to_string(),
+                prior,
+            });
+        }
+        Ok(())
+    }
+    fn add_var_node(&mut self, name: String, prior: HashMap<i16, f64>) -> PyResult<usize> {
+        let row = row_from_map(&prior, 2 * self.eta() + 1)?;
+        Ok(self.add_var_node_row(name, row)?)
     }
+    //flip_prob is the probability that the inequality is wrong
+    #[args(flip_prob = "0.0")]
     fn add_equation(
         &mut self,
         name: String,
         coefficients: Vec<i16>,
         value: i16,
         is_smaller: bool,
-        is_equal: bool
+        is_equal: bool,
+        flip_prob: f64,
     ) -> PyResult<usize> {
-        if self.var_nodes != K {
-            panic!("Wrong number of variables.");
+        let coeffs = self.coefficients(None, coefficients)?;
+        let op = cmp_operator(is_smaller, is_equal);
+        self.add_constraint(name, coeffs, value, op, flip_prob)
+    }
+    //Adds one inequality per row of coefficients (one column per variable) as
+    //in add_equation, with the i-th entries of values, is_smaller and is_equal;
+    //the check nodes are built with threads threads and named "Line i" by
+    //their position among all check nodes. Nothing is added if a row is invalid.
+    #[args(flip_prob = "0.0", threads = "1")]
+    fn add_equations(
+        &mut self,
+        py: Python,
+        coefficients: PyReadonlyArray2<i16>,
+        values: PyReadonlyArray1<i16>,
+        is_smaller: PyReadonlyArray1<bool>,
+        is_equal: PyReadonlyArray1<bool>,
+        flip_prob: f64,
+        threads: usize,
+    ) -> PyResult<Vec<usize>> {
+        if threads == 0 {
+            return Err(PyErr::from(CheckGraphError::new(
+                "Cannot work with less than 1 thread.".to_owned(),
+            )));
+        }
+        check_flip_prob(flip_prob)?;
+        if self.var_nodes != self.var_count {
+            return Err(PyErr::from(CheckGraphError::dimension(format!(
+                "Wrong number of variables (should be {} but is {}).",
+                self.var_count, self.var_nodes
+            ))));
         }
-        if coefficients.len() != self.var_nodes {
-            panic!(
+        let coefficients = coefficients.as_array();
+        let values = values.as_array();
+        let is_smaller = is_smaller.as_array();
+        let is_equal = is_equal.as_array();
+        let (rows, cols) = coefficients.dim();
+        if cols != self.var_nodes {
+            return Err(PyErr::from(CheckGraphError::dimension(format!(
                 "Wrong number of coefficients (should be {} but is {}).",
-                self.var_nodes,
-                coefficients.len()
-            );
+                self.var_nodes, cols
+            ))));
         }
-        let op = if is_smaller {
-            if is_equal {
-                CmpOperator::SmallerEq
-            }
-            else {
-                CmpOperator::Smaller
-            }
-        } else {
-            if is_equal {
-                CmpOperator::GreaterEq
+        for (name, len) in [
+            ("values", values.len()),
+            ("is_smaller", is_smaller.len()),
+            ("is_equal", is_equal.len()),
+        ]
+        .iter()
+        {
+            if *len != rows {
+                return Err(PyErr::from(CheckGraphError::dimension(format!(
+                    "Wrong length of {} (should be {} but is {}).",
+                    name, rows, len
+                ))));
             }
-            else {
-                CmpOperator::Greater
+        }
+        let offset = self.check_nodes.len();
+        let mut equations = Vec::with_capacity(rows);
+        for (i, row) in coefficients.outer_iter().enumerate() {
+            let coeffs: Vec<(usize, i16)> = row.iter().cloned().enumerate().collect();
+            let size = required_fft_size(&coeffs, self.eta());
+            if self.fft_size.is_none() && size > MAX_FFT_SIZE {
+                return Err(PyErr::from(CheckGraphError::dimension(format!(
+                    "Equation Line {} needs a transform of size {} (maximum is {}).",
+                    offset + i,
+                    size,
+                    MAX_FFT_SIZE
+                ))));
             }
-        };
-        let check_node: CheckNode<K, ETA> =
-            CheckNode::new(coefficients.try_into().unwrap(), value, op, N);
-        let idx = self.g.add_node(name, Box::new(check_node));
-        for n in 0..self.var_nodes {
-            self.g
-                .add_edge(n, idx)
-                .map_err(|e| CheckGraphError::from_bp(e))?;
+            equations.push((coeffs, values[i], cmp_operator(is_smaller[i], is_equal[i])));
         }
-        Ok(idx)
+        let residuals: Vec<Arc<Residual>> = (0..rows).map(|_| Arc::new(Residual::new())).collect();
+        let schedules: Vec<NodeSchedule> = residuals
+            .iter()
+            .enumerate()
+            .map(|(i, residual)| NodeSchedule {
+                settings: self.settings.clone(),
+                order: self.residuals.len() + i,
+                residual: residual.clone(),
+            })
+            .collect();
+        let fft_size = self.fft_size;
+        let graph = &mut self.g;
+        let eqs = &equations;
+        let indices = py.allow_threads(|| {
+            dispatch!(graph, g => add_check_nodes(g, offset, eqs, flip_prob, fft_size, schedules, threads))
+        })?;
+        self.residuals.extend(residuals);
+        self.check_nodes.extend(indices.iter().cloned());
+        for (i, (coeffs, value, op)) in equations.into_iter().enumerate() {
+            self.nodes.push(NodeDef::Check {
+                name: format!("Line {}", offset + i),
+                coeffs,
+                value,
+                op,
+                flip_prob,
+            });
+        }
+        Ok(indices)
+    }
+    //Only connects the check node to the variables with non-zero coefficients
+    #[args(flip_prob = "0.0")]
+    fn add_equation_sparse(
+        &mut self,
+        name: String,
+        indices: Vec<usize>,
+        coefficients: Vec<i16>,
+        value: i16,
+        is_smaller: bool,
+        is_equal: bool,
+        flip_prob: f64,
+    ) -> PyResult<usize> {
+        let coeffs = self.coefficients(Some(indices), coefficients)?;
+        let op = cmp_operator(is_smaller, is_equal);
+        self.add_constraint(name, coeffs, value, op, flip_prob)
+    }
+    //<a,x> = value
+    //If indices are given, coefficients are sparse as in add_equation_sparse
+    #[args(indices = "None", flip_prob = "0.0")]
+    fn add_equality(
+        &mut self,
+        name: String,
+        coefficients: Vec<i16>,
+        value: i16,
+        indices: Option<Vec<usize>>,
+        flip_prob: f64,
+    ) -> PyResult<usize> {
+        let coeffs = self.coefficients(indices, coefficients)?;
+        self.add_constraint(name, coeffs, value, CmpOperator::Equal, flip_prob)
+    }
+    //lo <= <a,x> <= hi
+    #[args(indices = "None", flip_prob = "0.0")]
+    fn add_interval(
+        &mut self,
+        name: String,
+        coefficients: Vec<i16>,
+        lo: i16,
+        hi: i16,
+        indices: Option<Vec<usize>>,
+        flip_prob: f64,
+    ) -> PyResult<usize> {
+        if lo > hi {
+            return Err(PyErr::from(CheckGraphError::new(format!(
+                "Empty interval [{}, {}].",
+                lo, hi
+            ))));
+        }
+        let coeffs = self.coefficients(indices, coefficients)?;
+        self.add_constraint(name, coeffs, 0, CmpOperator::Interval(lo, hi), flip_prob)
+    }
+    //<a,x> = value mod modulus
+    #[args(indices = "None", flip_prob = "0.0")]
+    fn add_modular_equation(
+        &mut self,
+        name: String,
+        coefficients: Vec<i16>,
+        value: i16,
+        modulus: i16,
+        indices: Option<Vec<usize>>,
+        flip_prob: f64,
+    ) -> PyResult<usize> {
+        if modulus <= 0 {
+            return Err(PyErr::from(CheckGraphError::new(format!(
+                "Modulus has to be positive but is {}.",
+                modulus
+            ))));
+        }
+        let coeffs = self.coefficients(indices, coefficients)?;
+        let value = value.rem_euclid(modulus);
+        self.add_constraint(name, coeffs, value, CmpOperator::Modular(modulus), flip_prob)
     }
     fn ini(&mut self) -> PyResult<()> {
-        self.g
+        dispatch!(&mut self.g, g => g
             .initialize()
-            .map_err(|e| CheckGraphError::from_bp(e))?;
+            .map_err(|e| CheckGraphError::from_bp(e))?);
+        self.initialized = true;
         Ok(())
     }
-    fn propagate(&mut self, steps: usize, threads: u32) -> PyResult<()> {
-        if threads <= 0 {
+    //Writes priors, equations, fixed values, settings and the current
+    //messages to path, load continues the propagation from there
+    fn save(&mut self, path: &str) -> PyResult<()> {
+        let messages = if self.initialized {
+            self.check_messages()?
+        } else {
+            Vec::new()
+        };
+        let checkpoint = Checkpoint {
+            var_count: self.var_count,
+            eta: self.eta(),
+            log_domain: self.log_domain(),
+            damping: self.damping(),
+            schedule: self.schedule,
+            step: self.step,
+            track_statistics: self.track_statistics,
+            fft_size: self.fft_size,
+            nodes: self.nodes.clone(),
+            fixed: self.fixed.iter().map(|(n, v)| (*n, *v)).collect(),
+            evidence: self.evidence.iter().map(|(n, e)| (*n, e.clone())).collect(),
+            initialized: self.initialized,
+            messages: messages,
+        };
+        std::fs::write(path, checkpoint.to_bytes())
+            .map_err(|e| CheckGraphError::new(format!("Could not write {}: {}", path, e)))?;
+        Ok(())
+    }
+    #[staticmethod]
+    fn load(path: &str) -> PyResult<CheckGraph> {
+        let data = std::fs::read(path)
+            .map_err(|e| CheckGraphError::new(format!("Could not read {}: {}", path, e)))?;
+        let checkpoint = Checkpoint::from_bytes(&data)?;
+        let mut graph = CheckGraph::new(checkpoint.var_count, checkpoint.eta, checkpoint.log_domain)?;
+        graph.set_damping(checkpoint.damping)?;
+        graph.settings.set_schedule(checkpoint.schedule);
+        graph.schedule = checkpoint.schedule;
+        graph.step = checkpoint.step;
+        graph.track_statistics = checkpoint.track_statistics;
+        graph.set_fft_size(checkpoint.fft_size)?;
+        for node in checkpoint.nodes {
+            match node {
+                NodeDef::Var { name, prior } => {
+                    graph.add_var_node_row(name, prior)?;
+                }
+                NodeDef::Check {
+                    name,
+                    coeffs,
+                    value,
+                    op,
+                    flip_prob,
+                } => {
+                    graph.add_constraint(name, coeffs, value, op, flip_prob)?;
+                }
+            }
+        }
+        for (node, value) in checkpoint.fixed {
+            graph.set_fixed(node, value)?;
+        }
+        for (node, likelihoods) in checkpoint.evidence {
+            graph.set_evidence(node, likelihoods)?;
+        }
+        if checkpoint.initialized {
+            graph.ini()?;
+            graph.restore_messages(checkpoint.messages)?;
+        }
+        Ok(graph)
+    }
+    //Runs without holding the GIL and checks for signals between steps, so
+    //Ctrl-C raises KeyboardInterrupt after the current step; the graph stays
+    //usable and continues from there. callback(done, steps) is called after
+    //every step and must not access the graph.
+    #[args(callback = "None")]
+    fn propagate(
+        &mut self,
+        py: Python,
+        steps: usize,
+        threads: usize,
+        callback: Option<PyObject>,
+    ) -> PyResult<()> {
+        if threads == 0 {
             return Err(PyErr::from(CheckGraphError::new(
                 "Cannot work with less than 1 thread.".to_owned(),
             )));
-        } else if threads == 1 {
-            self.g
-                .propagate(steps)
-                .map_err(|e| CheckGraphError::from_bp(e))?;
-        } else {
-            self.g
-                .propagate_threaded(steps, threads)
-                .map_err(|e| CheckGraphError::from_bp(e))?;
         }
+        self.propagate_interruptible(py, steps, threads, (0, steps), callback.as_ref())?;
+        if self.track_statistics {
+            py.allow_threads(|| self.record_statistics(threads))?;
+        }
+        Ok(())
+    }
+    //Propagates steps steps per iteration until the largest change of a
+    //marginal is at most tolerance, returns whether that happened within
+    //max_iterations; statistics are recorded for every iteration. Signals and
+    //callback as in propagate, with all max_iterations*steps steps counted.
+    #[args(steps = "2", threads = "1", callback = "None")]
+    fn propagate_until(
+        &mut self,
+        py: Python,
+        max_iterations: usize,
+        tolerance: f64,
+        steps: usize,
+        threads: usize,
+        callback: Option<PyObject>,
+    ) -> PyResult<bool> {
+        if threads == 0 {
+            return Err(PyErr::from(CheckGraphError::new(
+                "Cannot work with less than 1 thread.".to_owned(),
+            )));
+        }
+        let total = max_iterations * steps;
+        for iteration in 0..max_iterations {
+            let progress = (iteration * steps, total);
+            self.propagate_interruptible(py, steps, threads, progress, callback.as_ref())?;
+            let stats = py.allow_threads(|| self.record_statistics(threads))?;
+            if stats.max_change.map_or(false, |c| c <= tolerance) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+    //Alternates propagating steps steps and fixing the variables whose most
+    //likely value has probability at least min_prob, whose entropy is at most
+    //max_entropy and whose most likely value did not change for stable_rounds
+    //rounds (at most max_per_round per round, most likely first). Variables
+    //fixed here are released if the probability of their value drops below
+    //unfix_prob or if propagating after fixing them runs into contradicting
+    //messages (the messages from before are restored then); released values
+    //are not fixed again. Stops after rounds rounds or once all variables are
+    //fixed. Signals and callback(done, total steps) as in propagate.
+    #[args(
+        rounds = "10",
+        steps = "2",
+        threads = "1",
+        min_prob = "0.95",
+        max_entropy = "None",
+        stable_rounds = "2",
+        max_per_round = "None",
+        unfix_prob = "0.05",
+        callback = "None"
+    )]
+    fn decimate(
+        &mut self,
+        py: Python,
+        rounds: usize,
+        steps: usize,
+        threads: usize,
+        min_prob: f64,
+        max_entropy: Option<f64>,
+        stable_rounds: usize,
+        max_per_round: Option<usize>,
+        unfix_prob: f64,
+        callback: Option<PyObject>,
+    ) -> PyResult<DecimationReport> {
+        if threads == 0 {
+            return Err(PyErr::from(CheckGraphError::new(
+                "Cannot work with less than 1 thread.".to_owned(),
+            )));
+        }
+        if !self.initialized {
+            return Err(PyErr::from(CheckGraphError::new(
+                "Graph has to be initialized before decimation.".to_owned(),
+            )));
+        }
+        if !(min_prob > 0.0 && min_prob <= 1.0 && unfix_prob >= 0.0 && unfix_prob < min_prob) {
+            return Err(PyErr::from(CheckGraphError::new(format!(
+                "Invalid thresholds min_prob {} and unfix_prob {} (need 0 <= unfix_prob < min_prob <= 1).",
+                min_prob, unfix_prob
+            ))));
+        }
+        let settings = DecimationSettings {
+            min_prob,
+            max_entropy,
+            stable_rounds,
+            max_per_round,
+            unfix_prob,
+        };
+        let eta = self.eta() as i16;
+        let total = rounds * steps;
+        let mut decimation = Decimation::new(settings, self.var_nodes);
+        let mut last_batch: Vec<(usize, i16)> = Vec::new();
+        for round in 0..rounds {
+            decimation.report.rounds = round + 1;
+            //Only contradictions caused by the last batch can be undone
+            let snapshot = if last_batch.is_empty() {
+                None
+            } else {
+                Some(self.check_messages()?)
+            };
+            let mut contradiction = false;
+            for step in 0..steps {
+                match py.allow_threads(|| self.propagate_step(threads)) {
+                    Err(e) if e.kind == ErrorKind::DegenerateDistribution && snapshot.is_some() => {
+                        contradiction = true;
+                        break;
+                    }
+                    res => res?,
+                }
+                py.check_signals()?;
+                if let Some(callback) = callback.as_ref() {
+                    callback.call1(py, (round * steps + step + 1, total))?;
+                }
+            }
+            if let (true, Some(snapshot)) = (contradiction, snapshot) {
+                self.restore_messages(snapshot)?;
+                for (node, value) in last_batch.drain(..) {
+                    self.unfix_node(node)?;
+                    decimation.record_unfixed(node, value, round);
+                }
+                decimation.report.contradictions += 1;
+                continue;
+            }
+            let (marginals, entropies) = self.marginals(py, None, threads)?;
+            decimation.update(&marginals);
+            for (node, value) in decimation.unlikely(&marginals, eta) {
+                self.unfix_node(node)?;
+                decimation.record_unfixed(node, value, round);
+            }
+            last_batch = decimation.candidates(&marginals, &entropies, &self.fixed, eta);
+            for (node, value) in last_batch.iter() {
+                self.set_fixed(*node, *value)?;
+                decimation.record_fixed(*node, *value, round);
+            }
+            if self.fixed.len() == self.var_nodes {
+                break;
+            }
+        }
+        Ok(decimation.report)
+    }
+    #[getter]
+    fn statistics(&self) -> Vec<IterationStats> {
+        self.stats.clone()
+    }
+    #[getter]
+    fn last_statistics(&self) -> Option<IterationStats> {
+        self.stats.last().cloned()
+    }
+    fn clear_statistics(&mut self) {
+        self.stats.clear();
+        self.last_marginals = None;
+    }
+    //Messages from check nodes are mixed with their previous ones:
+    //(1-damping)*new + damping*old
+    #[getter]
+    fn damping(&self) -> f64 {
+        self.settings.damping()
+    }
+    #[setter]
+    fn set_damping(&mut self, damping: f64) -> PyResult<()> {
+        if !(damping >= 0.0 && damping < 1.0) {
+            return Err(PyErr::from(CheckGraphError::new(format!(
+                "Damping {} not in [0, 1).",
+                damping
+            ))));
+        }
+        self.settings.set_damping(damping);
+        Ok(())
+    }
+    #[getter]
+    fn fft_size(&self) -> Option<usize> {
+        self.fft_size
+    }
+    #[setter]
+    fn set_fft_size(&mut self, fft_size: Option<usize>) -> PyResult<()> {
+        if let Some(n) = fft_size {
+            if !n.is_power_of_two() || n < 2 || n > MAX_FFT_SIZE {
+                return Err(PyErr::from(CheckGraphError::dimension(format!(
+                    "FFT size {} is not a power of two in [2, {}].",
+                    n, MAX_FFT_SIZE
+                ))));
+            }
+        }
+        self.fft_size = fft_size;
+        Ok(())
+    }
+    //Check nodes whose transform is smaller than the worst case (fixed
+    //fft_size) measure the probability mass that wraps around in every
+    //update, this is the largest share
+    #[getter]
+    fn aliased_mass(&self) -> f64 {
+        self.settings.aliased_mass()
+    }
+    fn reset_aliased_mass(&mut self) {
+        self.settings.reset_aliased_mass();
+    }
+    #[getter]
+    fn schedule(&self) -> String {
+        match self.schedule {
+            Schedule::Flooding => "flooding".to_owned(),
+            Schedule::Serial(_) => "serial".to_owned(),
+            Schedule::Residual(_) => "residual".to_owned(),
+        }
+    }
+    //schedule is one of
+    //  flooding: all check nodes update in every step
+    //  serial: check nodes are split into groups which update in turns
+    //  residual: only the fraction of check nodes whose messages changed the most update
+    #[args(groups = "2", fraction = "0.1")]
+    fn set_schedule(&mut self, schedule: &str, groups: usize, fraction: f64) -> PyResult<()> {
+        let schedule = match schedule {
+            "flooding" => Schedule::Flooding,
+            "serial" if groups > 0 => Schedule::Serial(groups),
+            "residual" if fraction > 0.0 && fraction <= 1.0 => Schedule::Residual(fraction),
+            "serial" => {
+                return Err(PyErr::from(CheckGraphError::new(
+                    "Serial schedule needs at least one group.".to_owned(),
+                )))
+            }
+            "residual" => {
+                return Err(PyErr::from(CheckGraphError::new(format!(
+                    "Fraction {} not in (0, 1].",
+                    fraction
+                ))))
+            }
+            _ => {
+                return Err(PyErr::from(CheckGraphError::new(format!(
+                    "Unknown schedule {} (supported are flooding, serial and residual).",
+                    schedule
+                ))))
+            }
+        };
+        self.settings.set_schedule(schedule);
+        self.schedule = schedule;
         Ok(())
     }
+    //Runs without holding the GIL
     fn get_results(
         &self,
+        py: Python,
         thread_count: usize,
     ) -> PyResult<HashMap<usize, Option<(HashMap<i16, Probability>, f64)>>> {
-        let res = fetch_results_parallel(&self.g, (0..self.var_nodes).collect(), thread_count)
-            .map_err(|e| CheckGraphError::from_bp(e))?;
+        if thread_count == 0 {
+            return Err(PyErr::from(CheckGraphError::new(
+                "Cannot work with less than 1 thread.".to_owned(),
+            )));
+        }
+        let nodes = (0..self.var_nodes).collect();
+        let graph = &self.g;
+        let res = py.allow_threads(|| {
+            dispatch!(graph, g => fetch_results_parallel(g, nodes, thread_count))
+        })?;
         Ok(res)
     }
     fn get_result(&self, node: usize) -> PyResult<HashMap<i16, f64>> {
-        let res = self
-            .g
-            .get_result(node)
-            .map_err(|e| CheckGraphError::from_bp(e))?
-            .ok_or(CheckGraphError::new(
-                "Node did not return a result.".to_owned(),
-            ))?;
-        let sum: f64 = res.values().sum();
-        //println!("Sum: {}", sum);
-        //println!("Res: {:?}\n", res);
-        Ok(res.into_iter().map(|(v, p)| (v, p / sum)).collect())
+        self.check_var_node(node)?;
+        let res = dispatch!(&self.g, g => fetch_result(g, node))?;
+        Ok(res)
+    }
+    //Returns (marginals, entropies, argmax, max_probs) for nodes (all variables
+    //if None): marginals has one row per node with column j holding the
+    //probability of j-eta, argmax is the most likely value and max_probs its
+    //probability. Runs without holding the GIL.
+    #[args(threads = "1", nodes = "None")]
+    fn get_marginals<'py>(
+        &self,
+        py: Python<'py>,
+        threads: usize,
+        nodes: Option<Vec<usize>>,
+    ) -> PyResult<(
+        &'py PyArray2<f64>,
+        &'py PyArray1<f64>,
+        &'py PyArray1<i16>,
+        &'py PyArray1<f64>,
+    )> {
+        let (marginals, entropies) = self.marginals(py, nodes, threads)?;
+        let rows = marginals.len();
+        let eta = self.eta() as i16;
+        let (argmaxs, max_probs): (Vec<i16>, Vec<f64>) = marginals
+            .iter()
+            .map(|marginal| {
+                let i = argmax(marginal);
+                (i as i16 - eta, marginal[i])
+            })
+            .unzip();
+        let flat: Vec<f64> = marginals.into_iter().flatten().collect();
+        Ok((
+            PyArray1::from_vec(py, flat).reshape([rows, 2 * eta as usize + 1])?,
+            PyArray1::from_vec(py, entropies),
+            PyArray1::from_vec(py, argmaxs),
+            PyArray1::from_vec(py, max_probs),
+        ))
     }
 }
 
-fn fetch_results_parallel(
-    g: &BPGraph<i16, CheckMsg<ETA>, CtrlMsg, CtrlMsgA>,
+impl CheckGraph {
+    //Marginals (probabilities of -eta..=eta) and entropies of nodes, all
+    //variables if None, fetched without holding the GIL
+    pub fn marginals(
+        &self,
+        py: Python,
+        nodes: Option<Vec<usize>>,
+        threads: usize,
+    ) -> PyResult<(Vec<Vec<f64>>, Vec<f64>)> {
+        if threads == 0 {
+            return Err(PyErr::from(CheckGraphError::new(
+                "Cannot work with less than 1 thread.".to_owned(),
+            )));
+        }
+        let nodes = match nodes {
+            Some(nodes) => {
+                for node in nodes.iter() {
+                    self.check_var_node(*node)?;
+                }
+                nodes
+            }
+            None => (0..self.var_nodes).collect(),
+        };
+        let eta = self.eta() as i16;
+        let graph = &self.g;
+        Ok(py.allow_threads(|| fetch_marginals(graph, nodes, eta, threads))?)
+    }
+    //The bulk APIs add all var_count variable nodes at once
+    fn check_no_var_nodes(&self) -> Result<(), CheckGraphError> {
+        if self.var_nodes != 0 {
+            return Err(CheckGraphError::new(format!(
+                "Variable nodes already added ({}).",
+                self.var_nodes
+            )));
+        }
+        Ok(())
+    }
+    fn check_var_node(&self, node: usize) -> Result<(), CheckGraphError> {
+        if node < self.var_nodes {
+            Ok(())
+        } else {
+            Err(CheckGraphError::node_not_found(node, self.var_nodes))
+        }
+    }
+    //Checks the coefficients of an equation and pairs them with their variable nodes
+    //Dense coefficients are connected to all variables, sparse ones only if non-zero
+    fn coefficients(
+        &self,
+        indices: Option<Vec<usize>>,
+        coefficients: Vec<i16>,
+    ) -> Result<Vec<(usize, i16)>, CheckGraphError> {
+        if self.var_nodes != self.var_count {
+            return Err(CheckGraphError::dimension(format!(
+                "Wrong number of variables (should be {} but is {}).",
+                self.var_count, self.var_nodes
+            )));
+        }
+        let indices = match indices {
+            Some(indices) => indices,
+            None => {
+                if coefficients.len() != self.var_nodes {
+                    return Err(CheckGraphError::dimension(format!(
+                        "Wrong number of coefficients (should be {} but is {}).",
+                        self.var_nodes,
+                        coefficients.len()
+                    )));
+                }
+                return Ok(coefficients.into_iter().enumerate().collect());
+            }
+        };
+        if indices.len() != coefficients.len() {
+            return Err(CheckGraphError::dimension(format!(
+                "Number of indices ({}) and coefficients ({}) differ.",
+                indices.len(),
+                coefficients.len()
+            )));
+        }
+        let mut coeffs: Vec<(usize, i16)> = indices
+            .into_iter()
+            .zip(coefficients.into_iter())
+            .filter(|(_, c)| *c != 0)
+            .collect();
+        coeffs.sort_by_key(|(i, _)| *i);
+        if coeffs.is_empty() {
+            return Err(CheckGraphError::new(
+                "Equation has no non-zero coefficient.".to_owned(),
+            ));
+        }
+        if let Some((i, _)) = coeffs.iter().find(|(i, _)| *i >= self.var_nodes) {
+            return Err(CheckGraphError::node_not_found(*i, self.var_nodes));
+        }
+        if let Some(w) = coeffs.windows(2).find(|w| w[0].0 == w[1].0) {
+            return Err(CheckGraphError::new(format!(
+                "Variable index {} given more than once.",
+                w[0].0
+            )));
+        }
+        Ok(coeffs)
+    }
+    fn add_constraint(
+        &mut self,
+        name: String,
+        coeffs: Vec<(usize, i16)>,
+        value: i16,
+        op: CmpOperator,
+        flip_prob: f64,
+    ) -> PyResult<usize> {
+        check_flip_prob(flip_prob)?;
+        let size = required_fft_size(&coeffs, self.eta());
+        if self.fft_size.is_none() && size > MAX_FFT_SIZE {
+            return Err(PyErr::from(CheckGraphError::dimension(format!(
+                "Equation {} needs a transform of size {} (maximum is {}).",
+                name, size, MAX_FFT_SIZE
+            ))));
+        }
+        let def = NodeDef::Check {
+            name: name.clone(),
+            coeffs: coeffs.clone(),
+            value,
+            op,
+            flip_prob,
+        };
+        let residual = Arc::new(Residual::new());
+        let schedule = NodeSchedule {
+            settings: self.settings.clone(),
+            order: self.residuals.len(),
+            residual: residual.clone(),
+        };
+        let fft_size = self.fft_size;
+        let idx = dispatch!(&mut self.g, g => add_check_node(g, name, coeffs, value, op, flip_prob, fft_size, schedule))?;
+        self.residuals.push(residual);
+        self.check_nodes.push(idx);
+        self.nodes.push(def);
+        Ok(idx)
+    }
+    fn add_var_node_row(&mut self, name: String, prior: Vec<f64>) -> Result<usize, CheckGraphError> {
+        let idx = dispatch!(&mut self.g, g => add_var_node(g, name.clone(), prior_from_row(&prior)?))?;
+        self.var_nodes += 1;
+        self.nodes.push(NodeDef::Var { name, prior });
+        Ok(idx)
+    }
+    //Raw messages every check node sent last, in order of insertion
+    fn check_messages(&mut self) -> Result<Vec<Vec<(usize, Vec<f64>)>>, CheckGraphError> {
+        let mut messages = Vec::with_capacity(self.check_nodes.len());
+        for idx in self.check_nodes.clone() {
+            let response = dispatch!(&mut self.g, g => g
+                .send_control_message(idx, CtrlMsg::GetMessages)
+                .map_err(|e| CheckGraphError::from_bp(e).with_node(idx))?);
+            match response {
+                CtrlMsgA::Messages(msgs) => messages.push(msgs),
+                _ => {
+                    return Err(CheckGraphError::new("Node did not return its messages.".to_owned())
+                        .with_node(idx))
+                }
+            }
+        }
+        Ok(messages)
+    }
+    fn unfix_node(&mut self, node: usize) -> Result<(), CheckGraphError> {
+        dispatch!(&mut self.g, g => g
+            .send_control_message(node, CtrlMsg::ClearFixed)
+            .map_err(|e| CheckGraphError::from_bp(e).with_node(node))?);
+        self.fixed.remove(&node);
+        Ok(())
+    }
+    //messages[i] holds the raw messages check node i sent last, variable
+    //nodes get them as their inbox
+    fn restore_messages(&mut self, messages: Vec<Vec<(usize, Vec<f64>)>>) -> Result<(), CheckGraphError> {
+        if messages.len() != self.check_nodes.len() {
+            return Err(CheckGraphError::new(format!(
+                "Invalid checkpoint: messages of {} check nodes given but there are {}.",
+                messages.len(),
+                self.check_nodes.len()
+            )));
+        }
+        let mut inboxes: BTreeMap<usize, Vec<(usize, Vec<f64>)>> = BTreeMap::new();
+        for (idx, msgs) in self.check_nodes.clone().into_iter().zip(messages.into_iter()) {
+            if msgs.is_empty() {
+                continue;
+            }
+            for (node, msg) in msgs.iter() {
+                inboxes.entry(*node).or_insert_with(Vec::new).push((idx, msg.clone()));
+            }
+            dispatch!(&mut self.g, g => g
+                .send_control_message(idx, CtrlMsg::SetMessages(msgs))
+                .map_err(|e| CheckGraphError::from_bp(e).with_node(idx))?);
+        }
+        for (node, inbox) in inboxes {
+            self.check_var_node(node)?;
+            dispatch!(&mut self.g, g => g
+                .send_control_message(node, CtrlMsg::SetMessages(inbox))
+                .map_err(|e| CheckGraphError::from_bp(e).with_node(node))?);
+        }
+        Ok(())
+    }
+    //Propagates single steps, progress is (steps done before, total steps)
+    //as reported to callback
+    fn propagate_interruptible(
+        &mut self,
+        py: Python,
+        steps: usize,
+        threads: usize,
+        progress: (usize, usize),
+        callback: Option<&PyObject>,
+    ) -> PyResult<()> {
+        let (done, total) = progress;
+        for step in 0..steps {
+            py.allow_threads(|| self.propagate_step(threads))?;
+            py.check_signals()?;
+            if let Some(callback) = callback {
+                callback.call1(py, (done + step + 1, total))?;
+            }
+        }
+        Ok(())
+    }
+    fn propagate_step(&mut self, threads: usize) -> Result<(), CheckGraphError> {
+        //Serial and residual schedules are updated between single steps
+        self.settings.set_step(self.step);
+        if threads == 1 {
+            dispatch!(&mut self.g, g => g
+                .propagate(1)
+                .map_err(|e| CheckGraphError::from_bp(e))?);
+        } else {
+            dispatch!(&mut self.g, g => g
+                .propagate_threaded(1, threads as u32)
+                .map_err(|e| CheckGraphError::from_bp(e))?);
+        }
+        self.step += 1;
+        if let Schedule::Residual(fraction) = self.schedule {
+            let residuals = self.residuals.iter().map(|r| r.get()).collect();
+            self.settings
+                .set_residual_threshold(residual_threshold(residuals, fraction));
+        }
+        Ok(())
+    }
+    fn record_statistics(&mut self, threads: usize) -> Result<IterationStats, CheckGraphError> {
+        let nodes = (0..self.var_nodes).collect();
+        let (marginals, entropies) = fetch_marginals(&self.g, nodes, self.eta() as i16, threads)?;
+        let stats = iteration_stats(
+            self.stats.len(),
+            self.step,
+            self.last_marginals.as_ref(),
+            &marginals,
+            &entropies,
+        );
+        self.last_marginals = Some(marginals);
+        self.stats.push(stats.clone());
+        Ok(stats)
+    }
+}
+
+fn new_graph<M: CheckMessage>() -> Graph<M> {
+    let mut g = BPGraph::new();
+    g.set_normalize(false);
+    g
+}
+
+fn check_probability(v: i16, p: f64) -> Result<(), CheckGraphError> {
+    if p.is_finite() && p >= 0.0 {
+        Ok(())
+    } else {
+        Err(CheckGraphError::new(format!(
+            "Invalid prior probability {} for value {}.",
+            p, v
+        )))
+    }
+}
+
+//size is 2*eta+1
+fn row_from_map(prior: &HashMap<i16, f64>, size: usize) -> Result<Vec<f64>, CheckGraphError> {
+    let eta = (size / 2) as i16;
+    let mut probs = vec![0.0; size];
+    for (v, p) in prior {
+        if v.abs() > eta {
+            return Err(CheckGraphError::dimension(format!(
+                "Value {} of prior out of range (eta is {}).",
+                v, eta
+            )));
+        }
+        check_probability(*v, *p)?;
+        probs[(v + eta) as usize] = *p;
+    }
+    Ok(probs)
+}
+
+//Probabilities (or likelihoods) of -eta..=eta, size is 2*eta+1
+fn check_row(row: &[f64], size: usize) -> Result<(), CheckGraphError> {
+    if row.len() != size {
+        return Err(CheckGraphError::dimension(format!(
+            "Wrong length of prior (should be {} but is {}).",
+            size,
+            row.len()
+        )));
+    }
+    for (i, p) in row.iter().enumerate() {
+        check_probability(i as i16 - (size / 2) as i16, *p)?;
+    }
+    Ok(())
+}
+
+fn prior_from_row<M: CheckMessage>(row: &[f64]) -> Result<M, CheckGraphError> {
+    check_row(row, M::SIZE)?;
+    Ok(M::from_probabilities(row))
+}
+
+fn add_var_node<M: CheckMessage>(
+    g: &mut Graph<M>,
+    name: String,
+    mut prior_msg: M,
+) -> Result<usize, CheckGraphError> {
+    let mut n = BinVariableNode::new();
+    prior_msg
+        .normalize()
+        .map_err(|_| CheckGraphError::degenerate("Prior is zero everywhere.".to_owned()))?;
+    n.set_prior(&prior_msg)
+        .map_err(|e| CheckGraphError::from_bp(e))?;
+    Ok(g.add_node(name, Box::new(n)))
+}
+
+//Validates all rows before adding any node, returns the number of nodes added
+fn add_var_nodes_from_rows<M: CheckMessage>(
+    g: &mut Graph<M>,
+    priors: &Vec<Vec<f64>>,
+) -> Result<usize, CheckGraphError> {
+    let mut msgs = Vec::with_capacity(priors.len());
+    for (i, row) in priors.iter().enumerate() {
+        let mut msg: M = prior_from_row(row).map_err(|e| e.with_node(i))?;
+        msg.normalize().map_err(|_| {
+            CheckGraphError::degenerate("Prior is zero everywhere.".to_owned()).with_node(i)
+        })?;
+        msgs.push(msg);
+    }
+    for (i, msg) in msgs.into_iter().enumerate() {
+        add_var_node(g, i.to_string(), msg)?;
+    }
+    Ok(priors.len())
+}
+
+fn cmp_operator(is_smaller: bool, is_equal: bool) -> CmpOperator {
+    match (is_smaller, is_equal) {
+        (true, true) => CmpOperator::SmallerEq,
+        (true, false) => CmpOperator::Smaller,
+        (false, true) => CmpOperator::GreaterEq,
+        (false, false) => CmpOperator::Greater,
+    }
+}
+
+fn check_flip_prob(flip_prob: f64) -> Result<(), CheckGraphError> {
+    if flip_prob >= 0.0 && flip_prob <= 1.0 {
+        Ok(())
+    } else {
+        Err(CheckGraphError::new(format!(
+            "Flip probability {} not in [0, 1].",
+            flip_prob
+        )))
+    }
+}
+
+//coefficients: (variable node, coefficient)
+fn add_check_node<M: CheckMessage>(
+    g: &mut Graph<M>,
+    name: String,
+    coefficients: Vec<(usize, i16)>,
+    value: i16,
+    op: CmpOperator,
+    flip_prob: f64,
+    fft_size: Option<usize>,
+    schedule: NodeSchedule,
+) -> Result<usize, CheckGraphError> {
+    let var_nodes: Vec<usize> = coefficients.iter().map(|(n, _)| *n).collect();
+    let check_node: CheckNode<M> =
+        CheckNode::new(coefficients, value, op, flip_prob, fft_size, schedule);
+    insert_check_node(g, name, &var_nodes, check_node)
+}
+
+fn insert_check_node<M: CheckMessage>(
+    g: &mut Graph<M>,
+    name: String,
+    var_nodes: &[usize],
+    check_node: CheckNode<M>,
+) -> Result<usize, CheckGraphError> {
+    let idx = g.add_node(name, Box::new(check_node));
+    for n in var_nodes {
+        g.add_edge(*n, idx)
+            .map_err(|e| CheckGraphError::from_bp(e))?;
+    }
+    Ok(idx)
+}
+
+//Builds the check nodes of equations (coefficients, value, operator) in
+//parallel and adds them in order, named "Line i" starting at offset
+fn add_check_nodes<M: CheckMessage>(
+    g: &mut Graph<M>,
+    offset: usize,
+    equations: &[(Vec<(usize, i16)>, i16, CmpOperator)],
+    flip_prob: f64,
+    fft_size: Option<usize>,
+    schedules: Vec<NodeSchedule>,
+    thread_count: usize,
+) -> Result<Vec<usize>, CheckGraphError> {
+    let per_thread = std::cmp::max(1, (equations.len() + thread_count - 1) / thread_count);
+    let mut schedules = schedules.into_iter();
+    let check_nodes: Vec<CheckNode<M>> = crossbeam::scope(|scope| -> Result<Vec<CheckNode<M>>, CheckGraphError> {
+        let mut handles = Vec::new();
+        for chunk in equations.chunks(per_thread) {
+            let chunk_schedules: Vec<NodeSchedule> = schedules.by_ref().take(chunk.len()).collect();
+            handles.push(scope.spawn(move |_| -> Vec<CheckNode<M>> {
+                chunk
+                    .iter()
+                    .zip(chunk_schedules.into_iter())
+                    .map(|((coeffs, value, op), schedule)| {
+                        CheckNode::new(coeffs.clone(), *value, *op, flip_prob, fft_size, schedule)
+                    })
+                    .collect()
+            }));
+        }
+        let mut check_nodes = Vec::with_capacity(equations.len());
+        for h in handles {
+            check_nodes.extend(h.join().map_err(|_| {
+                CheckGraphError::new("Joining threads failed in add_equations.".to_owned())
+            })?);
+        }
+        Ok(check_nodes)
+    })
+    .map_err(|_| CheckGraphError::new("Scoped threading failed.".to_owned()))??;
+    let mut indices = Vec::with_capacity(equations.len());
+    for (i, ((coeffs, _, _), check_node)) in equations.iter().zip(check_nodes.into_iter()).enumerate() {
+        let var_nodes: Vec<usize> = coeffs.iter().map(|(n, _)| *n).collect();
+        indices.push(insert_check_node(g, format!("Line {}", offset + i), &var_nodes, check_node)?);
+    }
+    Ok(indices)
+}
+
+//Returns the normalized marginal of a variable node
+fn fetch_result<M: CheckMessage>(
+    g: &Graph<M>,
+    node: usize,
+) -> Result<HashMap<i16, Probability>, CheckGraphError> {
+    let mut res = g
+        .get_result(node)
+        .map_err(|e| CheckGraphError::from_bp(e).with_node(node))?
+        .ok_or(CheckGraphError::new("Node did not return a result.".to_owned()).with_node(node))?;
+    let sum: f64 = res.values().sum();
+    if !(sum > 0.0) || !sum.is_finite() {
+        return Err(CheckGraphError::degenerate(format!(
+            "{}: result sums up to {}.",
+            DEGENERATE_DISTRIBUTION, sum
+        ))
+        .with_node(node));
+    }
+    res.iter_mut().for_each(|(_, p)| *p /= sum);
+    Ok(res)
+}
+
+fn fetch_results_parallel<M: CheckMessage>(
+    g: &Graph<M>,
     nodes: Vec<usize>,
     thread_count: usize,
-) -> BPResult<HashMap<usize, Option<(HashMap<i16, Probability>, f64)>>> {
+) -> Result<HashMap<usize, Option<(HashMap<i16, Probability>, f64)>>, CheckGraphError> {
     crossbeam::scope(
-        |scope| -> BPResult<HashMap<usize, Option<(HashMap<i16, Probability>, f64)>>> {
-            let nodes_per_thread = nodes.len() / thread_count;
+        |scope| -> Result<HashMap<usize, Option<(HashMap<i16, Probability>, f64)>>, CheckGraphError> {
+            let nodes_per_thread = std::cmp::max(1, (nodes.len() + thread_count - 1) / thread_count);
             let mut results = HashMap::new();
             let mut handles = Vec::new();
             for nodes_list in nodes.chunks(nodes_per_thread) {
                 handles.push(scope.spawn(
-                    move |_| -> Vec<(usize, BPResult<Option<(HashMap<i16, Probability>, f64)>>)> {
+                    move |_| -> Result<Vec<(usize, Option<(HashMap<i16, Probability>, f64)>)>, CheckGraphError> {
                         let mut tr_results = Vec::new();
                         for node in nodes_list {
-                            let res = g.get_result(*node).map(|res| match res {
-                                Some(mut r) => {
-                                    let sum: f64 = r.values().sum();
-                                    r.iter_mut().for_each(|(_, p)| *p /= sum);
-                                    let ent = calc_entropy(&r);
-                                    if ent.is_nan() {
-                                        println!("{:?}", r);
-                                        panic!("");
-                                    }
-                                    Some((r, ent))
-                                }
-                                None => panic!("Node {} did not return a result.", node),
-                            });
-                            tr_results.push((*node, res));
+                            let r = fetch_result(g, *node)?;
+                            let ent = calc_entropy(&r);
+                            if ent.is_nan() {
+                                return Err(CheckGraphError::degenerate(format!(
+                                    "{}: entropy of {:?} is NaN.",
+                                    DEGENERATE_DISTRIBUTION, r
+                                ))
+                                .with_node(*node));
+                            }
+                            tr_results.push((*node, Some((r, ent))));
                         }
-                        tr_results
+                        Ok(tr_results)
                     },
                 ));
             }
             for h in handles {
-                let tr = h.join().expect("Joining threads failed in get_results.");
-                for (node, res) in tr.into_iter() {
-                    results.insert(node, res?);
-                }
+                let tr = h.join().map_err(|_| {
+                    CheckGraphError::new("Joining threads failed in get_results.".to_owned())
+                })??;
+                results.extend(tr);
             }
             Ok(results)
         },
     )
-    .expect("Scoped threading failed.")
+    .map_err(|_| CheckGraphError::new("Scoped threading failed.".to_owned()))?
+}
+
+//Rows of probabilities of -eta..=eta and the entropies of nodes, in order
+fn fetch_marginals(
+    graph: &GraphVariant,
+    nodes: Vec<usize>,
+    eta: i16,
+    thread_count: usize,
+) -> Result<(Vec<Vec<f64>>, Vec<f64>), CheckGraphError> {
+    let results = dispatch!(graph, g => fetch_results_parallel(g, nodes.clone(), thread_count))?;
+    let mut marginals = Vec::with_capacity(nodes.len());
+    let mut entropies = Vec::with_capacity(nodes.len());
+    for node in nodes {
+        let (res, ent) = results
+            .get(&node)
+            .and_then(|r| r.as_ref())
+            .ok_or(CheckGraphError::new("Node did not return a result.".to_owned()).with_node(node))?;
+        marginals.push((-eta..=eta).map(|v| *res.get(&v).unwrap_or(&0.0)).collect());
+        entropies.push(*ent);
+    }
+    Ok((marginals, entropies))
 }
 
 fn calc_entropy(probs: &HashMap<i16, Probability>) -> f64 {
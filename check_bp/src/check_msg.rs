@@ -1,11 +1,36 @@
 use belief_propagation::{BPError, BPResult, Msg};
-use rustfft::{num_complex::Complex, Fft};
 use std::fmt::Debug;
-use std::sync::Arc;
+
+//Nodes report distributions that are zero everywhere through BPError, the
+//description starts with this so they can be told apart from other errors
+pub const DEGENERATE_DISTRIBUTION: &str = "Degenerate distribution";
+
+//Distribution over -eta..=eta as used by the nodes, implemented by CheckMsg
+//and LogCheckMsg
+pub trait CheckMessage:
+    Msg<i16> + Copy + Clone + PartialEq + Debug + Send + Sync + 'static
+{
+    //2*eta+1
+    const SIZE: usize;
+    //probs[i] is the probability of i-eta
+    fn from_probabilities(probs: &[f64]) -> Self;
+    //Probabilities up to a common factor, the largest one is not 0 unless the
+    //message is degenerate
+    fn probabilities(&self) -> Vec<f64>;
+    fn fixed(value: i16) -> Self;
+    //Pointwise product, not normalized
+    fn product(&self, other: &Self) -> Self;
+    //All values have probability 0
+    fn is_degenerate(&self) -> bool;
+    //Internal representation, from_raw(raw()) restores the message exactly
+    fn raw(&self) -> Vec<f64>;
+    fn from_raw(raw: &[f64]) -> BPResult<Self>;
+
+}
 
 //N = 2^k!
 //ETA is actually (eta-1)/2, for eta=2 use 5!
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct CheckMsg<const ETA: usize> {
     pub data: [f64; ETA],
 }
@@ -32,39 +57,20 @@ impl<const ETA: usize> CheckMsg<ETA> {
         }
         self[value] = 1.0;
     }
-    pub fn mult_and_transform(
-        self,
-        scalar: i16,
-        size: usize,
-        fft: &Arc<dyn Fft<f64>>,
-    ) -> Vec<Complex<f64>> {
-        let mut indata = vec![
-            Complex {
-                re: 0 as f64,
-                im: 0 as f64
-            };
-            size
-        ];
-        for (v, p) in self {
-            let mut idx = scalar * v;
-            //TODO: Avoid if/move out of loop
-            if idx < 0 {
-                idx += size as i16;
-            }
-            indata[idx as usize] += Complex { re: p, im: 0.0 };
-        }
-        fft.process(&mut indata);
-        indata
-    }
     pub fn len(&self) -> usize {
         self.data.len()
     }
-    pub fn to_probabilities(&mut self) {
+    pub fn to_probabilities(&mut self) -> BPResult<()> {
         let sum: f64 = self.data.iter().sum();
         if sum == 0.0 || sum.is_nan() {
-            panic!("No valid message encountered in to_probabilities.");
+            return Err(BPError::new(
+                "CheckMsg::to_probabilities".to_owned(),
+                format!("{}: message sums up to {}.", DEGENERATE_DISTRIBUTION, sum),
+            )
+            .attach_debug_object("data (the message)", self.data.to_vec()));
         }
         self.data.iter_mut().for_each(|p| *p /= sum);
+        Ok(())
     }
 }
 
@@ -91,11 +97,14 @@ impl<const ETA: usize> Msg<i16> for CheckMsg<ETA> {
                 .unwrap_or(&f64::NAN)
         };
         if max == 0 as f64 || max.is_nan() {
-            println!("Dist: {:?}", self.data.to_vec());
             return Err(BPError::new(
                 "CheckMsg::Msg".to_owned(),
-                "Did not find a useful value to normalize by".to_owned(),
-            ));
+                format!(
+                    "{}: did not find a useful value to normalize by",
+                    DEGENERATE_DISTRIBUTION
+                ),
+            )
+            .attach_debug_object("data (the distribution)", self.data.to_vec()));
         }
         self.data.iter_mut().for_each(|p| *p /= max);
         Ok(())
@@ -108,6 +117,43 @@ impl<const ETA: usize> Msg<i16> for CheckMsg<ETA> {
     }
 }
 
+impl<const ETA: usize> CheckMessage for CheckMsg<ETA> {
+    const SIZE: usize = ETA;
+    fn from_probabilities(probs: &[f64]) -> Self {
+        let mut data = [0.0; ETA];
+        data.copy_from_slice(probs);
+        CheckMsg { data: data }
+    }
+    fn probabilities(&self) -> Vec<f64> {
+        self.data.to_vec()
+    }
+    fn fixed(value: i16) -> Self {
+        let mut msg = CheckMsg::new();
+        msg.set_fixed_value(value);
+        msg
+    }
+    fn product(&self, other: &Self) -> Self {
+        let mut res = *self;
+        res.mult_msg(other);
+        res
+    }
+    fn is_degenerate(&self) -> bool {
+        self.data.iter().all(|p| *p == 0.0)
+    }
+    fn raw(&self) -> Vec<f64> {
+        self.data.to_vec()
+    }
+    fn from_raw(raw: &[f64]) -> BPResult<Self> {
+        if raw.len() != ETA {
+            return Err(BPError::new(
+                "CheckMsg::from_raw".to_owned(),
+                format!("Wrong length {} of message (should be {}).", raw.len(), ETA),
+            ));
+        }
+        Ok(Self::from_probabilities(raw))
+    }
+}
+
 impl<const ETA: usize> std::ops::Index<i16> for CheckMsg<ETA> {
     type Output = f64;
     fn index(&self, val: i16) -> &Self::Output {
@@ -1,117 +1,173 @@
-use crate::bin_tree::BinMultTree;
-use crate::bin_var_node::{CtrlMsg, CtrlMsgA};
-use crate::check_msg::CheckMsg;
+use crate::bin_var_node::{messages_from_raw, CtrlMsg, CtrlMsgA};
+use crate::check_msg::{CheckMessage, DEGENERATE_DISTRIBUTION};
+use crate::schedule::NodeSchedule;
 use belief_propagation::{BPError, BPResult, NodeFunction, NodeIndex, Msg};
-use rustfft::{num_complex::Complex, Fft, FftPlanner};
+use realfft::{num_complex::Complex, ComplexToReal, RealFftPlanner, RealToComplex};
+use std::marker::PhantomData;
 use std::sync::Arc;
 
+#[derive(Copy, Clone, Debug)]
 pub enum CmpOperator {
     SmallerEq,
     GreaterEq,
     Smaller,
     Greater,
+    //<a,x> = value
+    Equal,
+    //lo <= <a,x> <= hi, value is unused
+    Interval(i16, i16),
+    //<a,x> = value mod q
+    Modular(i16),
 }
 
-pub struct CheckNode<const K: usize, const ETA: usize> {
+pub struct CheckNode<M: CheckMessage> {
     n: usize,
-    coeffs: [i16; K],
+    //Smallest size without any wraparound, n is only smaller if fixed by the graph
+    required_n: usize,
+    //(variable node, coefficient), sorted by node index
+    coeffs: Vec<(NodeIndex, i16)>,
     value: i16,
     op: CmpOperator,
-    fft: Arc<dyn Fft<f64>>,
-    ifft: Arc<dyn Fft<f64>>,
+    flip_prob: f64,
+    transforms: Transforms,
+    //Spectra of the incoming messages, same order as coeffs, recomputed only
+    //once the message differs from the one in inputs (e.g. not for fixed
+    //variables or converged edges)
+    spectra: Vec<Vec<Complex<f64>>>,
+    inputs: Vec<Option<M>>,
+    //Buffers of an update: prefix[j] is the product of the spectra of the
+    //first j+1 messages, suffix the one of the messages after the current,
+    //partial the one of all messages but the current and dist_sum its inverse
+    prefix: Vec<Vec<Complex<f64>>>,
+    suffix: Vec<Complex<f64>>,
+    partial: Vec<Complex<f64>>,
+    dist_sum: Vec<f64>,
     connections: Vec<usize>,
+    //Messages sent in the last update, same order as coeffs
+    last: Vec<Option<M>>,
+    schedule: NodeSchedule,
+    msg_type: PhantomData<M>,
+}
+
+//Index of value in data, clamped to [0, len] so that sums over values
+//outside of the support are empty or complete
+fn clamped_index(data: &Vec<f64>, value: i32) -> usize {
+    (value + data.len() as i32 / 2).max(0).min(data.len() as i32) as usize
 }
 
 fn pdf_le(data: &Vec<f64>, value: i16) -> f64 {
-    data[..=(value + data.len() as i16 / 2) as usize]
-        .iter()
-        .sum()
+    data[..clamped_index(data, value as i32 + 1)].iter().sum()
 }
 fn pdf_ge(data: &Vec<f64>, value: i16) -> f64 {
-    data[(value + data.len() as i16 / 2) as usize..]
-        .iter()
-        .sum()
+    data[clamped_index(data, value as i32)..].iter().sum()
 }
 
 fn pdf_l(data: &Vec<f64>, value: i16) -> f64 {
-    data[..(value + data.len() as i16 / 2) as usize]
-        .iter()
-        .sum()
+    data[..clamped_index(data, value as i32)].iter().sum()
 }
 fn pdf_g(data: &Vec<f64>, value: i16) -> f64 {
-    data[(1+value + data.len() as i16 / 2) as usize..]
-        .iter()
-        .sum()
+    data[clamped_index(data, value as i32 + 1)..].iter().sum()
 }
 
-fn derive_from_inequality_greater_eq<const ETA: usize>(
-    dist_sum: Vec<f64>,
-    value: i16,
-    coeff: i16,
-) -> CheckMsg<ETA> {
-    let mut result = CheckMsg::new();
-    //coeff*v+sum >=< value
-    for v in -(ETA as i16) / 2..=(ETA as i16) / 2 {
-        let vc = coeff * v;
-        result[v] += pdf_ge(&dist_sum, value - vc);
+fn pdf_eq(data: &Vec<f64>, value: i16) -> f64 {
+    let idx = value as i32 + data.len() as i32 / 2;
+    if idx < 0 || idx >= data.len() as i32 {
+        0.0
+    } else {
+        data[idx as usize]
     }
-    result.normalize().expect("Failed to normalize");
-    result
 }
 
-fn derive_from_inequality_smaller_eq<const ETA: usize>(
-    dist_sum: Vec<f64>,
-    value: i16,
-    coeff: i16,
-) -> CheckMsg<ETA> {
-    let mut result = CheckMsg::new();
-    //coeff*v+sum >=< value
-    for v in -(ETA as i16) / 2..=(ETA as i16) / 2 {
-        let vc = coeff * v;
-        result[v] += pdf_le(&dist_sum, value - vc);
+fn pdf_interval(data: &Vec<f64>, lo: i16, hi: i16) -> f64 {
+    let half = data.len() as i32 / 2;
+    let lo = (lo as i32 + half).max(0);
+    let hi = (hi as i32 + half).min(data.len() as i32 - 1);
+    if lo > hi {
+        0.0
+    } else {
+        data[lo as usize..=hi as usize].iter().sum()
     }
-    result.normalize().expect("Failed to normalize");
-    result
 }
 
-fn derive_from_inequality_greater<const ETA: usize>(
-    dist_sum: Vec<f64>,
-    value: i16,
-    coeff: i16,
-) -> CheckMsg<ETA> {
-    let mut result = CheckMsg::new();
-    //coeff*v+sum >=< value
-    for v in -(ETA as i16) / 2..=(ETA as i16) / 2 {
-        let vc = coeff * v;
-        result[v] += pdf_g(&dist_sum, value - vc);
+fn pdf_mod(data: &Vec<f64>, value: i16, q: i16) -> f64 {
+    let start = (value as i32 + data.len() as i32 / 2).rem_euclid(q as i32) as usize;
+    data[start..].iter().step_by(q as usize).sum()
+}
+
+//Probability that the constraint holds given the sum of all other inputs
+//is distributed as data and the input contributes vc
+fn likelihood(data: &Vec<f64>, op: CmpOperator, value: i16, vc: i16) -> f64 {
+    match op {
+        CmpOperator::GreaterEq => pdf_ge(data, value - vc),
+        CmpOperator::SmallerEq => pdf_le(data, value - vc),
+        CmpOperator::Greater => pdf_g(data, value - vc),
+        CmpOperator::Smaller => pdf_l(data, value - vc),
+        CmpOperator::Equal => pdf_eq(data, value - vc),
+        CmpOperator::Interval(lo, hi) => pdf_interval(data, lo - vc, hi - vc),
+        CmpOperator::Modular(q) => pdf_mod(data, value - vc, q),
     }
-    result.normalize().expect("Failed to normalize");
-    result
 }
 
-fn derive_from_inequality_smaller<const ETA: usize>(
-    dist_sum: Vec<f64>,
+//Mixes the likelihood of the constraint holding and of it failing, an
+//answer of the fault oracle is assumed to be wrong with flip_prob
+fn derive_from_inequality<M: CheckMessage>(
+    dist_sum: &Vec<f64>,
     value: i16,
     coeff: i16,
-) -> CheckMsg<ETA> {
-    let mut result = CheckMsg::new();
+    op: CmpOperator,
+    flip_prob: f64,
+) -> BPResult<M> {
+    let mut probs = Vec::with_capacity(M::SIZE);
+    let total: f64 = if flip_prob > 0.0 {
+        dist_sum.iter().sum()
+    } else {
+        0.0
+    };
     //coeff*v+sum >=< value
-    for v in -(ETA as i16) / 2..=(ETA as i16) / 2 {
+    for v in -(M::SIZE as i16) / 2..=(M::SIZE as i16) / 2 {
         let vc = coeff * v;
-        result[v] += pdf_l(&dist_sum, value - vc);
+        let holds = likelihood(dist_sum, op, value, vc);
+        probs.push((1.0 - flip_prob) * holds + flip_prob * (total - holds));
     }
-    result.normalize().expect("Failed to normalize");
-    result
+    let mut result = M::from_probabilities(&probs);
+    result.normalize()?;
+    Ok(result)
 }
 
-fn multiply_pointwise(op0: &Vec<Complex<f64>>, op1: &Vec<Complex<f64>>) -> Vec<Complex<f64>> {
-    let prod: Vec<Complex<f64>> = op0
-        .iter()
-        .zip(op1.iter())
-        .map(|(p0, p1)| (*p0 * *p1))
+//Probabilities normalized to sum up to 1
+fn distribution<M: CheckMessage>(msg: &M) -> Vec<f64> {
+    let probs = msg.probabilities();
+    let sum: f64 = probs.iter().sum();
+    probs.into_iter().map(|p| p / sum).collect()
+}
+
+fn msg_residual<M: CheckMessage>(new: &M, old: &M) -> f64 {
+    distribution(new)
+        .into_iter()
+        .zip(distribution(old).into_iter())
+        .map(|(p_new, p_old)| (p_new - p_old).abs())
+        .fold(0.0, f64::max)
+}
+
+//(1-damping)*new + damping*old
+fn damp<M: CheckMessage>(new: &M, old: &M, damping: f64) -> BPResult<M> {
+    let mixed: Vec<f64> = distribution(new)
+        .into_iter()
+        .zip(distribution(old).into_iter())
+        .map(|(p_new, p_old)| (1.0 - damping) * p_new + damping * p_old)
         .collect();
+    let mut res = M::from_probabilities(&mixed);
+    res.normalize()?;
+    Ok(res)
+}
+
+//acc*op, scaled such that the largest absolute value is 1
+fn multiply_pointwise(acc: &mut [Complex<f64>], op: &[Complex<f64>]) {
+    acc.iter_mut()
+        .zip(op.iter())
+        .for_each(|(p0, p1)| *p0 *= *p1);
 
-    let max: f64 = prod
+    let max: f64 = acc
         .iter()
         .max_by(|p0, p1| {
             p0.norm()
@@ -120,29 +176,77 @@ fn multiply_pointwise(op0: &Vec<Complex<f64>>, op1: &Vec<Complex<f64>>) -> Vec<C
         })
         .map(|p| p.norm())
         .unwrap_or(f64::NAN);
+    //Degenerate products are left as they are, ifft reports them
     if max.is_nan() || max == 0.0 {
-        panic!("Could not normalize in fft domain.");
+        return;
     }
-    prod.into_iter().map(|p| p / max).collect()
+    acc.iter_mut().for_each(|p| *p /= max);
+}
+
+//Real transforms of one size, the input buffers are reused between calls
+struct Transforms {
+    n: usize,
+    r2c: Arc<dyn RealToComplex<f64>>,
+    c2r: Arc<dyn ComplexToReal<f64>>,
+    real_buf: Vec<f64>,
+    //The inverse overwrites its input, spectra are copied here first
+    complex_buf: Vec<Complex<f64>>,
 }
 
-fn ifft(mut data: Vec<Complex<f64>>, ifft: &Arc<dyn Fft<f64>>) -> Vec<f64> {
-    ifft.process(&mut data);
-    let res_temp: Vec<f64> = data.into_iter().map(|c| c.re).collect();
-    let mut res: Vec<f64> = vec![0 as f64; res_temp.len()];
-    let sz = res_temp.len();
-    let n = res_temp.len() / 2;
-    for (i, v) in res_temp[..n].into_iter().enumerate() {
-        res[i + n] = *v / sz as f64;
+impl Transforms {
+    fn new(n: usize) -> Self {
+        let mut planner = RealFftPlanner::<f64>::new();
+        let r2c = planner.plan_fft_forward(n);
+        let c2r = planner.plan_fft_inverse(n);
+        let real_buf = r2c.make_input_vec();
+        let complex_buf = r2c.make_output_vec();
+        Transforms {
+            n,
+            r2c,
+            c2r,
+            real_buf,
+            complex_buf,
+        }
+    }
+    //Buffer for a half spectrum (n/2+1 values)
+    fn spectrum_vec(&self) -> Vec<Complex<f64>> {
+        self.r2c.make_output_vec()
     }
-    for (i, v) in res_temp[n..].into_iter().enumerate() {
-        res[i] = *v / sz as f64;
+    //Half spectrum of the distribution of coeff*x for x distributed as msg,
+    //values outside of -n/2..n/2-1 wrap around
+    fn transform<M: CheckMessage>(
+        &mut self,
+        msg: &M,
+        coeff: i16,
+        spectrum: &mut [Complex<f64>],
+    ) -> BPResult<()> {
+        self.real_buf.iter_mut().for_each(|p| *p = 0.0);
+        let half = (M::SIZE / 2) as i64;
+        for (i, p) in msg.probabilities().into_iter().enumerate() {
+            let idx = (coeff as i64 * (i as i64 - half)).rem_euclid(self.n as i64);
+            self.real_buf[idx as usize] += p;
+        }
+        self.r2c
+            .process(&mut self.real_buf, spectrum)
+            .map_err(|e| BPError::new("CheckNode::transform".to_owned(), e.to_string()))
+    }
+    //Distribution of -n/2..n/2-1 from a half spectrum, scaled such that the
+    //largest probability is 1
+    fn inverse(&mut self, spectrum: &[Complex<f64>], res: &mut [f64]) -> BPResult<()> {
+        self.complex_buf.copy_from_slice(spectrum);
+        //These are zero for real input, products may leave rounding errors
+        self.complex_buf[0].im = 0.0;
+        self.complex_buf[self.n / 2].im = 0.0;
+        self.c2r
+            .process(&mut self.complex_buf, res)
+            .map_err(|e| BPError::new("CheckNode::inverse".to_owned(), e.to_string()))?;
+        //Index 0 holds the value 0, move -n/2 to the front
+        res.rotate_left(self.n / 2);
+        to_probabilities(res)
     }
-    to_probabilities(&mut res);
-    res
 }
 
-fn to_probabilities(data: &mut Vec<f64>) {
+fn to_probabilities(data: &mut [f64]) -> BPResult<()> {
     let max = *{
         data.iter()
             .max_by(|p0, p1| p0.partial_cmp(p1).unwrap_or(std::cmp::Ordering::Less))
@@ -150,141 +254,303 @@ fn to_probabilities(data: &mut Vec<f64>) {
     };
 
     if max == 0.0 || max.is_nan() {
-        panic!("No valid message encountered in to_probabilities.");
+        return Err(BPError::new(
+            "CheckNode::to_probabilities".to_owned(),
+            format!("{}: no valid message after the inverse fft.", DEGENERATE_DISTRIBUTION),
+        ));
     }
     data.iter_mut().for_each(|p| *p /= max);
+    Ok(())
 }
 
-impl<const K: usize, const ETA: usize> CheckNode<K, ETA> {
-    pub fn new(coeffs: [i16; K], value: i16, op: CmpOperator, n: usize) -> Self {
-        let mut planner = FftPlanner::new();
-        let fft = planner.plan_fft_forward(n);
-        let ifft = planner.plan_fft_inverse(n);
+//Smallest power of two transform size that holds sum c_i*x_i for |x_i| <= eta
+//without wraparound, the transform covers -n/2..n/2-1
+pub fn required_fft_size(coeffs: &[(NodeIndex, i16)], eta: usize) -> usize {
+    let support: usize = coeffs
+        .iter()
+        .map(|(_, c)| c.unsigned_abs() as usize * eta)
+        .sum();
+    (2 * support + 2).next_power_of_two()
+}
+
+impl<M: CheckMessage> CheckNode<M> {
+    //fft_size overrides required_fft_size
+    pub fn new(
+        mut coeffs: Vec<(NodeIndex, i16)>,
+        value: i16,
+        op: CmpOperator,
+        flip_prob: f64,
+        fft_size: Option<usize>,
+        schedule: NodeSchedule,
+    ) -> Self {
+        coeffs.sort_by_key(|(node, _)| *node);
+        let required_n = required_fft_size(&coeffs, M::SIZE / 2);
+        let n = fft_size.unwrap_or(required_n);
+        let k = coeffs.len();
+        let transforms = Transforms::new(n);
+        let spectrum = transforms.spectrum_vec();
         Self {
             n: n,
+            required_n: required_n,
             coeffs: coeffs,
             value: value,
             op: op,
-            connections: Vec::with_capacity(K),
-            fft,
-            ifft,
+            flip_prob: flip_prob,
+            connections: Vec::with_capacity(k),
+            spectra: vec![spectrum.clone(); k],
+            inputs: vec![None; k],
+            prefix: vec![spectrum.clone(); k.saturating_sub(1)],
+            suffix: spectrum.clone(),
+            partial: spectrum,
+            dist_sum: vec![0.0; n],
+            transforms: transforms,
+            last: vec![None; k],
+            schedule: schedule,
+            msg_type: PhantomData,
         }
     }
-    fn node_function_normal(
-        &self,
-        inbox: Vec<(NodeIndex, CheckMsg<ETA>)>,
-    ) -> BPResult<Vec<(NodeIndex, CheckMsg<ETA>)>> {
-
-        let leafs: Vec<Vec<Complex<f64>>> = inbox
+    fn position(&self, node_index: NodeIndex) -> BPResult<usize> {
+        self.coeffs
+            .binary_search_by_key(&node_index, |(node, _)| *node)
+            .map_err(|_| {
+                BPError::new(
+                    "CheckNode::coeff".to_owned(),
+                    format!("Node {} is not connected.", node_index),
+                )
+            })
+    }
+    fn coeff(&self, node_index: NodeIndex) -> BPResult<i16> {
+        self.position(node_index).map(|i| self.coeffs[i].1)
+    }
+    //Share of the probability mass of the sum over all inputs that lies
+    //outside of -n/2..n/2-1 and wraps around (plans a new transform)
+    fn aliased_mass(&self, inbox: &Vec<(NodeIndex, M)>, coeffs: &Vec<i16>) -> BPResult<f64> {
+        let size = self.required_n;
+        let mut transforms = Transforms::new(size);
+        let mut spectrum = transforms.spectrum_vec();
+        let mut prod = vec![Complex { re: 1.0, im: 0.0 }; size / 2 + 1];
+        for ((_, msg), coeff) in inbox.iter().zip(coeffs.iter()) {
+            transforms.transform(msg, *coeff, &mut spectrum)?;
+            multiply_pointwise(&mut prod, &spectrum);
+        }
+        let mut dist = vec![0.0; size];
+        transforms.inverse(&prod, &mut dist)?;
+        let total: f64 = dist.iter().sum();
+        let half = (self.n / 2) as i64;
+        let center = (size / 2) as i64;
+        let lost: f64 = dist
             .iter()
-            .map(|(node_index, msg)| {
-                msg.mult_and_transform(self.coeffs[*node_index], self.n, &self.fft)
-                    .iter()
-                    .map(|p| p / (self.n as f64).sqrt())
-                    .collect()
+            .enumerate()
+            .filter(|(i, _)| {
+                let v = *i as i64 - center;
+                v < -half || v >= half
             })
-            .collect();
+            .map(|(_, p)| *p)
+            .sum();
+        Ok(lost / total)
+    }
+    //Applies schedule and damping to the messages of node_function_normal
+    fn node_function_scheduled(
+        &mut self,
+        inbox: Vec<(NodeIndex, M)>,
+    ) -> BPResult<Vec<(NodeIndex, M)>> {
+        let positions = inbox
+            .iter()
+            .map(|(node_index, _)| self.position(*node_index))
+            .collect::<BPResult<Vec<usize>>>()?;
+        let nodes: Vec<NodeIndex> = inbox.iter().map(|(node_index, _)| *node_index).collect();
+        let last: Option<Vec<M>> = positions.iter().map(|i| self.last[*i]).collect();
+        //Under the residual schedule the residual of the last update decides,
+        //skipped nodes keep it and update again once the residuals of the
+        //others fall below it
+        let settings = &self.schedule.settings;
+        let skip = !settings.is_active(self.schedule.order)
+            || self.schedule.residual.get() < settings.residual_threshold();
+        let last = match last {
+            Some(last) if skip => {
+                return Ok(nodes.into_iter().zip(last.into_iter()).collect());
+            }
+            last => last,
+        };
+        let msgs = self.node_function_normal(inbox)?;
+        let msgs = match last {
+            None => {
+                self.schedule.residual.set(f64::INFINITY);
+                msgs
+            }
+            Some(last) => {
+                let residual = msgs
+                    .iter()
+                    .zip(last.iter())
+                    .map(|((_, new), old)| msg_residual(new, old))
+                    .fold(0.0, f64::max);
+                self.schedule.residual.set(residual);
+                let damping = self.schedule.settings.damping();
+                if damping > 0.0 {
+                    msgs.into_iter()
+                        .zip(last.iter())
+                        .map(|((node, new), old)| damp(&new, old, damping).map(|msg| (node, msg)))
+                        .collect::<BPResult<Vec<(NodeIndex, M)>>>()?
+                } else {
+                    msgs
+                }
+            }
+        };
+        for (i, (_, msg)) in positions.into_iter().zip(msgs.iter()) {
+            self.last[i] = Some(*msg);
+        }
+        Ok(msgs)
+    }
+    //Updates the spectrum of the incoming message at position i unless it is
+    //the same message as last time
+    fn update_spectrum(&mut self, i: usize, msg: &M) -> BPResult<()> {
+        if self.inputs[i] == Some(*msg) {
+            return Ok(());
+        }
+        self.inputs[i] = None;
+        self.transforms
+            .transform(msg, self.coeffs[i].1, &mut self.spectra[i])?;
+        self.inputs[i] = Some(*msg);
+        Ok(())
+    }
+    fn node_function_normal(
+        &mut self,
+        inbox: Vec<(NodeIndex, M)>,
+    ) -> BPResult<Vec<(NodeIndex, M)>> {
+        let coeffs = inbox
+            .iter()
+            .map(|(node_index, _)| self.coeff(*node_index))
+            .collect::<BPResult<Vec<i16>>>()?;
+        if self.n < self.required_n {
+            let mass = self.aliased_mass(&inbox, &coeffs)?;
+            self.schedule.settings.record_aliasing(mass);
+        }
 
-        let products: Vec<Vec<Complex<f64>>> =
-            BinMultTree::new(leafs, multiply_pointwise).calculate();
+        let k = inbox.len();
+        let mut msgs: Vec<M> = Vec::with_capacity(k);
+        if k == 1 {
+            //The sum over all other inputs is empty, i.e. 0 with probability 1
+            self.dist_sum.iter_mut().for_each(|p| *p = 0.0);
+            self.dist_sum[self.n / 2] = 1.0;
+            msgs.push(derive_from_inequality(
+                &self.dist_sum,
+                self.value,
+                coeffs[0],
+                self.op,
+                self.flip_prob,
+            )?);
+        } else {
+            let mut positions = Vec::with_capacity(k);
+            for (node_index, msg) in inbox.iter() {
+                let i = self.position(*node_index)?;
+                self.update_spectrum(i, msg)?;
+                positions.push(i);
+            }
+            self.prefix[0].copy_from_slice(&self.spectra[positions[0]]);
+            for j in 1..k - 1 {
+                let (done, todo) = self.prefix.split_at_mut(j);
+                todo[0].copy_from_slice(&done[j - 1]);
+                multiply_pointwise(&mut todo[0], &self.spectra[positions[j]]);
+            }
+            //Products of all messages but the j-th, from the last one down
+            self.suffix
+                .iter_mut()
+                .for_each(|p| *p = Complex { re: 1.0, im: 0.0 });
+            for j in (0..k).rev() {
+                if j == 0 {
+                    self.partial.copy_from_slice(&self.suffix);
+                } else {
+                    self.partial.copy_from_slice(&self.prefix[j - 1]);
+                    multiply_pointwise(&mut self.partial, &self.suffix);
+                    multiply_pointwise(&mut self.suffix, &self.spectra[positions[j]]);
+                }
+                self.transforms.inverse(&self.partial, &mut self.dist_sum)?;
+                msgs.push(derive_from_inequality(
+                    &self.dist_sum,
+                    self.value,
+                    coeffs[j],
+                    self.op,
+                    self.flip_prob,
+                )?);
+            }
+            msgs.reverse();
+        }
 
-        let partials: Vec<Vec<f64>> = products
+        Ok(inbox
             .into_iter()
-            .map(|prd| ifft(prd, &self.ifft))
-            .collect();
-
-        let res: Vec<(NodeIndex, CheckMsg<ETA>)> = match self.op {
-            CmpOperator::GreaterEq => partials
-                .into_iter()
-                .zip(inbox.into_iter())
-                .map(|(dist_sum, ib)| {
-                    (
-                        ib.0,
-                        derive_from_inequality_greater_eq(dist_sum, self.value, self.coeffs[ib.0]),
-                    )
-                })
-                .collect(),
-
-            CmpOperator::SmallerEq => partials
-                .into_iter()
-                .zip(inbox.into_iter())
-                .map(|(dist_sum, ib)| {
-                    (
-                        ib.0,
-                        derive_from_inequality_smaller_eq(dist_sum, self.value, self.coeffs[ib.0]),
-                    )
-                })
-                .collect(),
-
-            CmpOperator::Greater => partials
-                .into_iter()
-                .zip(inbox.into_iter())
-                .map(|(dist_sum, ib)| {
-                    (
-                        ib.0,
-                        derive_from_inequality_greater(dist_sum, self.value, self.coeffs[ib.0]),
-                    )
-                })
-                .collect(),
-
-            CmpOperator::Smaller => partials
-                .into_iter()
-                .zip(inbox.into_iter())
-                .map(|(dist_sum, ib)| {
-                    (
-                        ib.0,
-                        derive_from_inequality_smaller(dist_sum, self.value, self.coeffs[ib.0]),
-                    )
-                })
-                .collect(),
-        };
-        Ok(res)
+            .zip(msgs.into_iter())
+            .map(|((node_index, _), msg)| (node_index, msg))
+            .collect())
     }
 }
 
-impl<const K: usize, const ETA: usize> NodeFunction<i16, CheckMsg<ETA>, CtrlMsg, CtrlMsgA>
-    for CheckNode<K, ETA>
-{
+impl<M: CheckMessage> NodeFunction<i16, M, CtrlMsg, CtrlMsgA> for CheckNode<M> {
     fn node_function(
         &mut self,
-        inbox: Vec<(NodeIndex, CheckMsg<ETA>)>,
-    ) -> BPResult<Vec<(NodeIndex, CheckMsg<ETA>)>> {
-        self.node_function_normal(inbox)
+        inbox: Vec<(NodeIndex, M)>,
+    ) -> BPResult<Vec<(NodeIndex, M)>> {
+        self.node_function_scheduled(inbox)
     }
 
     fn number_inputs(&self) -> Option<usize> {
-        Some(K)
+        Some(self.coeffs.len())
     }
 
     fn is_factor(&self) -> bool {
         true
     }
-    fn get_prior(&self) -> Option<CheckMsg<ETA>> {
+    fn get_prior(&self) -> Option<M> {
         None
     }
     fn initialize(&mut self, connections: Vec<NodeIndex>) -> BPResult<()> {
-        //TODO: Ensure connections are sorted
-        if connections.len() != K {
-            Err(BPError::new(
+        if connections.len() != self.coeffs.len() {
+            return Err(BPError::new(
                 "CheckNode::initialize".to_owned(),
                 format!(
                     "Wrong number ({}) of connections given ({}).",
-                    K,
+                    self.coeffs.len(),
                     connections.len()
                 ),
-            ))
-        } else {
-            self.connections = connections;
-            Ok(())
+            ));
         }
+        for node in connections.iter() {
+            self.coeff(*node)?;
+        }
+        self.connections = connections;
+        Ok(())
     }
     fn reset(&mut self) -> BPResult<()> {
+        self.last.iter_mut().for_each(|msg| *msg = None);
+        self.inputs.iter_mut().for_each(|msg| *msg = None);
+        self.schedule.residual.set(f64::INFINITY);
         Ok(())
     }
+    fn send_control_message(&mut self, ctrl_msg: CtrlMsg) -> BPResult<CtrlMsgA> {
+        match ctrl_msg {
+            CtrlMsg::GetMessages => Ok(CtrlMsgA::Messages(
+                self.coeffs
+                    .iter()
+                    .zip(self.last.iter())
+                    .filter_map(|((node, _), msg)| msg.map(|msg| (*node, msg.raw())))
+                    .collect(),
+            )),
+            CtrlMsg::SetMessages(msgs) => {
+                for (node, msg) in messages_from_raw::<M>(msgs)? {
+                    let i = self.position(node)?;
+                    self.last[i] = Some(msg);
+                }
+                Ok(CtrlMsgA::default())
+            }
+            _ => Err(BPError::new(
+                "CheckNode::send_control_message".to_owned(),
+                "Check nodes cannot be fixed.".to_owned(),
+            )),
+        }
+    }
 
     fn is_ready(
         &self,
-        recv_from: &Vec<(NodeIndex, CheckMsg<ETA>)>,
+        recv_from: &Vec<(NodeIndex, M)>,
         _current_step: usize,
     ) -> BPResult<bool> {
         Ok(recv_from.len() == self.connections.len())
@@ -0,0 +1,334 @@
+use crate::check_graph::CheckGraphError;
+use crate::check_node::CmpOperator;
+use crate::schedule::Schedule;
+use std::convert::TryInto;
+
+const MAGIC: &[u8; 4] = b"CBPG";
+const VERSION: u32 = 1;
+
+//Nodes in order of insertion, replaying them restores the node indices
+#[derive(Clone)]
+pub enum NodeDef {
+    Var {
+        name: String,
+        //Probabilities of -eta..=eta
+        prior: Vec<f64>,
+    },
+    Check {
+        name: String,
+        coeffs: Vec<(usize, i16)>,
+        value: i16,
+        op: CmpOperator,
+        flip_prob: f64,
+    },
+}
+
+//Everything needed to restore a CheckGraph and continue propagation
+pub struct Checkpoint {
+    pub var_count: usize,
+    pub eta: usize,
+    pub log_domain: bool,
+    pub damping: f64,
+    pub schedule: Schedule,
+    pub step: usize,
+    pub track_statistics: bool,
+    pub fft_size: Option<usize>,
+    pub nodes: Vec<NodeDef>,
+    pub fixed: Vec<(usize, i16)>,
+    //Likelihoods of -eta..=eta per variable
+    pub evidence: Vec<(usize, Vec<f64>)>,
+    pub initialized: bool,
+    //Per check node (in order of insertion) the raw messages it sent last
+    pub messages: Vec<Vec<(usize, Vec<f64>)>>,
+}
+
+//All values are stored little endian, lengths as u64
+struct Writer {
+    data: Vec<u8>,
+}
+
+impl Writer {
+    fn u8(&mut self, v: u8) {
+        self.data.push(v);
+    }
+    fn bool(&mut self, v: bool) {
+        self.u8(v as u8);
+    }
+    fn i16(&mut self, v: i16) {
+        self.data.extend_from_slice(&v.to_le_bytes());
+    }
+    fn u32(&mut self, v: u32) {
+        self.data.extend_from_slice(&v.to_le_bytes());
+    }
+    fn usize(&mut self, v: usize) {
+        self.data.extend_from_slice(&(v as u64).to_le_bytes());
+    }
+    fn f64(&mut self, v: f64) {
+        self.data.extend_from_slice(&v.to_le_bytes());
+    }
+    fn str(&mut self, v: &str) {
+        self.usize(v.len());
+        self.data.extend_from_slice(v.as_bytes());
+    }
+    fn f64s(&mut self, v: &[f64]) {
+        self.usize(v.len());
+        v.iter().for_each(|x| self.f64(*x));
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+fn invalid(desc: &str) -> CheckGraphError {
+    CheckGraphError::new(format!("Invalid checkpoint: {}.", desc))
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], CheckGraphError> {
+        if self.data.len() - self.pos < len {
+            return Err(invalid("unexpected end of file"));
+        }
+        let res = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(res)
+    }
+    fn u8(&mut self) -> Result<u8, CheckGraphError> {
+        Ok(self.bytes(1)?[0])
+    }
+    fn bool(&mut self) -> Result<bool, CheckGraphError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(invalid("boolean out of range")),
+        }
+    }
+    fn i16(&mut self) -> Result<i16, CheckGraphError> {
+        Ok(i16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+    fn u32(&mut self) -> Result<u32, CheckGraphError> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+    fn usize(&mut self) -> Result<usize, CheckGraphError> {
+        let v = u64::from_le_bytes(self.bytes(8)?.try_into().unwrap());
+        v.try_into().map_err(|_| invalid("value out of range"))
+    }
+    //Number of following elements, each takes at least one byte
+    fn len(&mut self) -> Result<usize, CheckGraphError> {
+        let len = self.usize()?;
+        if len > self.data.len() - self.pos {
+            return Err(invalid("length out of range"));
+        }
+        Ok(len)
+    }
+    fn f64(&mut self) -> Result<f64, CheckGraphError> {
+        Ok(f64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+    fn str(&mut self) -> Result<String, CheckGraphError> {
+        let len = self.len()?;
+        String::from_utf8(self.bytes(len)?.to_vec()).map_err(|_| invalid("name is not utf-8"))
+    }
+    fn f64s(&mut self) -> Result<Vec<f64>, CheckGraphError> {
+        let len = self.len()?;
+        (0..len).map(|_| self.f64()).collect()
+    }
+}
+
+fn write_op(w: &mut Writer, op: CmpOperator) {
+    match op {
+        CmpOperator::SmallerEq => w.u8(0),
+        CmpOperator::GreaterEq => w.u8(1),
+        CmpOperator::Smaller => w.u8(2),
+        CmpOperator::Greater => w.u8(3),
+        CmpOperator::Equal => w.u8(4),
+        CmpOperator::Interval(lo, hi) => {
+            w.u8(5);
+            w.i16(lo);
+            w.i16(hi);
+        }
+        CmpOperator::Modular(q) => {
+            w.u8(6);
+            w.i16(q);
+        }
+    }
+}
+
+fn read_op(r: &mut Reader) -> Result<CmpOperator, CheckGraphError> {
+    Ok(match r.u8()? {
+        0 => CmpOperator::SmallerEq,
+        1 => CmpOperator::GreaterEq,
+        2 => CmpOperator::Smaller,
+        3 => CmpOperator::Greater,
+        4 => CmpOperator::Equal,
+        5 => CmpOperator::Interval(r.i16()?, r.i16()?),
+        6 => CmpOperator::Modular(r.i16()?),
+        _ => return Err(invalid("unknown operator")),
+    })
+}
+
+impl Checkpoint {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut w = Writer { data: Vec::new() };
+        w.data.extend_from_slice(MAGIC);
+        w.u32(VERSION);
+        w.usize(self.var_count);
+        w.usize(self.eta);
+        w.bool(self.log_domain);
+        w.f64(self.damping);
+        match self.schedule {
+            Schedule::Flooding => w.u8(0),
+            Schedule::Serial(groups) => {
+                w.u8(1);
+                w.usize(groups);
+            }
+            Schedule::Residual(fraction) => {
+                w.u8(2);
+                w.f64(fraction);
+            }
+        }
+        w.usize(self.step);
+        w.bool(self.track_statistics);
+        w.bool(self.fft_size.is_some());
+        w.usize(self.fft_size.unwrap_or(0));
+        w.usize(self.nodes.len());
+        for node in self.nodes.iter() {
+            match node {
+                NodeDef::Var { name, prior } => {
+                    w.u8(0);
+                    w.str(name);
+                    w.f64s(prior);
+                }
+                NodeDef::Check {
+                    name,
+                    coeffs,
+                    value,
+                    op,
+                    flip_prob,
+                } => {
+                    w.u8(1);
+                    w.str(name);
+                    w.usize(coeffs.len());
+                    for (node, coeff) in coeffs {
+                        w.usize(*node);
+                        w.i16(*coeff);
+                    }
+                    w.i16(*value);
+                    write_op(&mut w, *op);
+                    w.f64(*flip_prob);
+                }
+            }
+        }
+        w.usize(self.fixed.len());
+        for (node, value) in self.fixed.iter() {
+            w.usize(*node);
+            w.i16(*value);
+        }
+        w.usize(self.evidence.len());
+        for (node, likelihoods) in self.evidence.iter() {
+            w.usize(*node);
+            w.f64s(likelihoods);
+        }
+        w.bool(self.initialized);
+        w.usize(self.messages.len());
+        for msgs in self.messages.iter() {
+            w.usize(msgs.len());
+            for (node, msg) in msgs {
+                w.usize(*node);
+                w.f64s(msg);
+            }
+        }
+        w.data
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, CheckGraphError> {
+        let mut r = Reader { data, pos: 0 };
+        if r.bytes(MAGIC.len())? != MAGIC {
+            return Err(invalid("not a CheckGraph checkpoint"));
+        }
+        let version = r.u32()?;
+        if version != VERSION {
+            return Err(CheckGraphError::new(format!(
+                "Unsupported checkpoint version {} (supported is {}).",
+                version, VERSION
+            )));
+        }
+        let var_count = r.usize()?;
+        let eta = r.usize()?;
+        let log_domain = r.bool()?;
+        let damping = r.f64()?;
+        let schedule = match r.u8()? {
+            0 => Schedule::Flooding,
+            1 => Schedule::Serial(r.usize()?),
+            2 => Schedule::Residual(r.f64()?),
+            _ => return Err(invalid("unknown schedule")),
+        };
+        let step = r.usize()?;
+        let track_statistics = r.bool()?;
+        let fixed_fft_size = r.bool()?;
+        let n = r.usize()?;
+        let fft_size = if fixed_fft_size { Some(n) } else { None };
+        let node_count = r.len()?;
+        let mut nodes = Vec::new();
+        for _ in 0..node_count {
+            let node = match r.u8()? {
+                0 => NodeDef::Var {
+                    name: r.str()?,
+                    prior: r.f64s()?,
+                },
+                1 => {
+                    let name = r.str()?;
+                    let len = r.len()?;
+                    let coeffs = (0..len)
+                        .map(|_| Ok((r.usize()?, r.i16()?)))
+                        .collect::<Result<Vec<(usize, i16)>, CheckGraphError>>()?;
+                    NodeDef::Check {
+                        name,
+                        coeffs,
+                        value: r.i16()?,
+                        op: read_op(&mut r)?,
+                        flip_prob: r.f64()?,
+                    }
+                }
+                _ => return Err(invalid("unknown node type")),
+            };
+            nodes.push(node);
+        }
+        let fixed_count = r.len()?;
+        let fixed = (0..fixed_count)
+            .map(|_| Ok((r.usize()?, r.i16()?)))
+            .collect::<Result<Vec<(usize, i16)>, CheckGraphError>>()?;
+        let evidence_count = r.len()?;
+        let evidence = (0..evidence_count)
+            .map(|_| Ok((r.usize()?, r.f64s()?)))
+            .collect::<Result<Vec<(usize, Vec<f64>)>, CheckGraphError>>()?;
+        let initialized = r.bool()?;
+        let check_count = r.len()?;
+        let mut messages = Vec::new();
+        for _ in 0..check_count {
+            let len = r.len()?;
+            let msgs = (0..len)
+                .map(|_| Ok((r.usize()?, r.f64s()?)))
+                .collect::<Result<Vec<(usize, Vec<f64>)>, CheckGraphError>>()?;
+            messages.push(msgs);
+        }
+        if r.pos != data.len() {
+            return Err(invalid("trailing data"));
+        }
+        Ok(Checkpoint {
+            var_count,
+            eta,
+            log_domain,
+            damping,
+            schedule,
+            step,
+            track_statistics,
+            fft_size,
+            nodes,
+            fixed,
+            evidence,
+            initialized,
+            messages,
+        })
+    }
+}
@@ -0,0 +1,133 @@
+use crate::statistics::argmax;
+use pyo3::prelude::*;
+use pyo3::PyObjectProtocol;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+//When a variable is fixed or released, see CheckGraph.decimate
+pub struct DecimationSettings {
+    pub min_prob: f64,
+    pub max_entropy: Option<f64>,
+    pub stable_rounds: usize,
+    pub max_per_round: Option<usize>,
+    pub unfix_prob: f64,
+}
+
+#[pyclass]
+#[derive(Clone, Debug, Default)]
+pub struct DecimationReport {
+    #[pyo3(get)]
+    pub rounds: usize,
+    //(variable, value, round) in order of fixing, including released ones
+    #[pyo3(get)]
+    pub fixed: Vec<(usize, i16, usize)>,
+    //(variable, value, round) in order of releasing
+    #[pyo3(get)]
+    pub unfixed: Vec<(usize, i16, usize)>,
+    //Variables fixed by the decimation at the end
+    #[pyo3(get)]
+    pub values: HashMap<usize, i16>,
+    //Number of propagations that ran into contradicting messages
+    #[pyo3(get)]
+    pub contradictions: usize,
+}
+
+#[pyproto]
+impl PyObjectProtocol for DecimationReport {
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!(
+            "DecimationReport(rounds={}, fixed={}, unfixed={}, values={}, contradictions={})",
+            self.rounds,
+            self.fixed.len(),
+            self.unfixed.len(),
+            self.values.len(),
+            self.contradictions
+        ))
+    }
+}
+
+//State of a decimation between rounds
+pub struct Decimation {
+    settings: DecimationSettings,
+    last_argmax: Vec<Option<usize>>,
+    //Number of rounds the most likely value did not change
+    stable: Vec<usize>,
+    //Values that were released and are not fixed again
+    banned: HashSet<(usize, i16)>,
+    pub report: DecimationReport,
+}
+
+impl Decimation {
+    pub fn new(settings: DecimationSettings, var_count: usize) -> Self {
+        Decimation {
+            settings: settings,
+            last_argmax: vec![None; var_count],
+            stable: vec![0; var_count],
+            banned: HashSet::new(),
+            report: DecimationReport::default(),
+        }
+    }
+    //marginals[i] holds the probabilities of -eta..=eta of variable i
+    pub fn update(&mut self, marginals: &Vec<Vec<f64>>) {
+        for (i, marginal) in marginals.iter().enumerate() {
+            let current = argmax(marginal);
+            self.stable[i] = match self.last_argmax[i] {
+                Some(last) if last == current => self.stable[i] + 1,
+                _ => 1,
+            };
+            self.last_argmax[i] = Some(current);
+        }
+    }
+    //Variables fixed by the decimation whose value became unlikely
+    pub fn unlikely(&self, marginals: &Vec<Vec<f64>>, eta: i16) -> Vec<(usize, i16)> {
+        let mut res: Vec<(usize, i16)> = self
+            .report
+            .values
+            .iter()
+            .filter(|(node, value)| marginals[**node][(**value + eta) as usize] < self.settings.unfix_prob)
+            .map(|(node, value)| (*node, *value))
+            .collect();
+        res.sort();
+        res
+    }
+    //Variables to fix next with their most likely values, most likely first
+    pub fn candidates(
+        &self,
+        marginals: &Vec<Vec<f64>>,
+        entropies: &Vec<f64>,
+        fixed: &BTreeMap<usize, i16>,
+        eta: i16,
+    ) -> Vec<(usize, i16)> {
+        let mut candidates: Vec<(usize, i16, f64)> = marginals
+            .iter()
+            .enumerate()
+            .filter(|(node, _)| !fixed.contains_key(node))
+            .filter(|(node, _)| self.stable[*node] >= self.settings.stable_rounds)
+            .filter(|(node, _)| {
+                self.settings
+                    .max_entropy
+                    .map_or(true, |max_entropy| entropies[*node] <= max_entropy)
+            })
+            .map(|(node, marginal)| {
+                let i = argmax(marginal);
+                (node, i as i16 - eta, marginal[i])
+            })
+            .filter(|(node, value, p)| {
+                *p >= self.settings.min_prob && !self.banned.contains(&(*node, *value))
+            })
+            .collect();
+        candidates.sort_by(|c0, c1| c1.2.partial_cmp(&c0.2).unwrap_or(std::cmp::Ordering::Equal));
+        if let Some(max_per_round) = self.settings.max_per_round {
+            candidates.truncate(max_per_round);
+        }
+        candidates.into_iter().map(|(node, value, _)| (node, value)).collect()
+    }
+    pub fn record_fixed(&mut self, node: usize, value: i16, round: usize) {
+        self.report.fixed.push((node, value, round));
+        self.report.values.insert(node, value);
+    }
+    pub fn record_unfixed(&mut self, node: usize, value: i16, round: usize) {
+        self.report.unfixed.push((node, value, round));
+        self.report.values.remove(&node);
+        self.banned.insert((node, value));
+    }
+}
@@ -0,0 +1,248 @@
+use crate::check_graph::{CheckGraph, CheckGraphError};
+use crate::rank_estimation::log2_rank_bounds;
+use crate::statistics::argmax;
+use numpy::{PyReadonlyArray1, PyReadonlyArray2};
+use pyo3::prelude::*;
+use pyo3::PyObjectProtocol;
+
+//How well the marginals of one iteration recover a known key
+#[pyclass]
+#[derive(Clone, Debug)]
+pub struct KeyMetrics {
+    //Mean probability of the correct values
+    #[pyo3(get)]
+    pub avg_prob: f64,
+    //Mean number of values more likely than the correct one
+    #[pyo3(get)]
+    pub avg_rank: f64,
+    //Number of coefficients whose correct value is the most likely one
+    #[pyo3(get)]
+    pub correct: usize,
+    //Number of leading coefficients with rank 0 when sorted by the largest
+    //probability (descending), the entropy, the change of the entropy since
+    //the last evaluation (None for the first one) and the largest probability
+    //minus 2*entropy/(largest entropy) (descending); at most half of the key
+    #[pyo3(get)]
+    pub len_correct_prob: usize,
+    #[pyo3(get)]
+    pub len_correct_ent: usize,
+    #[pyo3(get)]
+    pub len_correct_ent_diff: Option<usize>,
+    #[pyo3(get)]
+    pub len_correct_prob_ent: usize,
+    //Whether one of the orders has half of the key correct
+    #[pyo3(get)]
+    pub success: bool,
+    #[pyo3(get)]
+    pub max_rank: usize,
+    //rank_counts[r] is the number of coefficients with rank r
+    #[pyo3(get)]
+    pub rank_counts: Vec<usize>,
+    //log2 of the product of (rank+1) over all coefficients, every key whose
+    //coefficients are all at least as likely as the correct ones ranks before
+    //the correct key (ignoring ties)
+    #[pyo3(get)]
+    pub log2_rank_lower_bound: f64,
+    //Lower and upper bound on log2 of the rank of the key among all keys
+    //ordered by probability, from histogram convolution
+    #[pyo3(get)]
+    pub log2_rank_bounds: (f64, f64),
+}
+
+#[pymethods]
+impl KeyMetrics {
+    #[getter]
+    fn len_correct_max(&self) -> usize {
+        self.len_correct_prob
+            .max(self.len_correct_ent)
+            .max(self.len_correct_ent_diff.unwrap_or(0))
+            .max(self.len_correct_prob_ent)
+    }
+}
+
+#[pyproto]
+impl PyObjectProtocol for KeyMetrics {
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!(
+            "KeyMetrics(avg_prob={}, avg_rank={}, correct={}, len_correct_prob={}, len_correct_ent={}, len_correct_ent_diff={:?}, len_correct_prob_ent={}, success={}, max_rank={}, log2_rank_lower_bound={}, log2_rank_bounds={:?})",
+            self.avg_prob,
+            self.avg_rank,
+            self.correct,
+            self.len_correct_prob,
+            self.len_correct_ent,
+            self.len_correct_ent_diff,
+            self.len_correct_prob_ent,
+            self.success,
+            self.max_rank,
+            self.log2_rank_lower_bound,
+            self.log2_rank_bounds
+        ))
+    }
+}
+
+//Evaluates marginals against the correct key, keeps the entropies of the
+//last evaluation for the entropy changes
+#[pyclass]
+pub struct KeyEvaluator {
+    key: Vec<i16>,
+    last_entropies: Option<Vec<f64>>,
+    //Histogram bins of the rank estimation up to the cost of the key, time
+    //and memory grow linearly, the bounds get tighter with more bins
+    #[pyo3(get)]
+    bins: usize,
+}
+
+#[pymethods]
+impl KeyEvaluator {
+    #[new]
+    #[args(bins = "16384")]
+    fn new(key: Vec<i16>, bins: usize) -> PyResult<Self> {
+        let mut evaluator = KeyEvaluator {
+            key: key,
+            last_entropies: None,
+            bins: 0,
+        };
+        evaluator.set_bins(bins)?;
+        Ok(evaluator)
+    }
+    #[setter]
+    fn set_bins(&mut self, bins: usize) -> PyResult<()> {
+        if bins == 0 {
+            return Err(PyErr::from(CheckGraphError::new(
+                "Number of bins has to be positive.".to_owned(),
+            )));
+        }
+        self.bins = bins;
+        Ok(())
+    }
+    //Uses the marginals of all variables of graph
+    #[args(threads = "1")]
+    fn evaluate(
+        &mut self,
+        py: Python,
+        graph: PyRef<CheckGraph>,
+        threads: usize,
+    ) -> PyResult<KeyMetrics> {
+        let (marginals, entropies) = graph.marginals(py, None, threads)?;
+        Ok(self.metrics(marginals, entropies)?)
+    }
+    //marginals and entropies as returned by CheckGraph.get_marginals
+    fn evaluate_marginals(
+        &mut self,
+        marginals: PyReadonlyArray2<f64>,
+        entropies: PyReadonlyArray1<f64>,
+    ) -> PyResult<KeyMetrics> {
+        let marginals = marginals
+            .as_array()
+            .outer_iter()
+            .map(|row| row.to_vec())
+            .collect();
+        let entropies = entropies.as_array().to_vec();
+        Ok(self.metrics(marginals, entropies)?)
+    }
+    //Forgets the entropies of the last evaluation
+    fn reset(&mut self) {
+        self.last_entropies = None;
+    }
+}
+
+impl KeyEvaluator {
+    fn metrics(
+        &mut self,
+        marginals: Vec<Vec<f64>>,
+        entropies: Vec<f64>,
+    ) -> Result<KeyMetrics, CheckGraphError> {
+        let n = self.key.len();
+        if marginals.len() != n || entropies.len() != n {
+            return Err(CheckGraphError::dimension(format!(
+                "Wrong number of marginals (should be {} but are {} with {} entropies).",
+                n,
+                marginals.len(),
+                entropies.len()
+            )));
+        }
+        let mut probs = Vec::with_capacity(n);
+        let mut ranks = Vec::with_capacity(n);
+        let mut max_probs = Vec::with_capacity(n);
+        for (node, (marginal, value)) in marginals.iter().zip(self.key.iter()).enumerate() {
+            let eta = (marginal.len() / 2) as i16;
+            let idx = value + eta;
+            if idx < 0 || idx as usize >= marginal.len() {
+                return Err(CheckGraphError::new(format!(
+                    "Key value {} out of range (eta is {}).",
+                    value, eta
+                ))
+                .with_node(node));
+            }
+            let prob = marginal[idx as usize];
+            probs.push(prob);
+            ranks.push(marginal.iter().filter(|p| **p > prob).count());
+            max_probs.push(marginal[argmax(marginal)]);
+        }
+        let max_rank = ranks.iter().cloned().max().unwrap_or(0);
+        let mut rank_counts = vec![0; max_rank + 1];
+        ranks.iter().for_each(|r| rank_counts[*r] += 1);
+
+        let len_correct_prob = correct_prefix(&ranks, &order_by(max_probs.iter().map(|p| -p)));
+        let len_correct_ent = correct_prefix(&ranks, &order_by(entropies.iter().cloned()));
+        let len_correct_ent_diff = self.last_entropies.as_ref().map(|last| {
+            let diffs = entropies.iter().zip(last.iter()).map(|(e, l)| (e - l).abs());
+            correct_prefix(&ranks, &order_by(diffs))
+        });
+        let max_ent = entropies.iter().cloned().fold(0.0, f64::max);
+        let max_ent = if max_ent > 0.0 { max_ent } else { 1.0 };
+        let len_correct_prob_ent = correct_prefix(
+            &ranks,
+            &order_by(
+                max_probs
+                    .iter()
+                    .zip(entropies.iter())
+                    .map(|(p, e)| -(p - 2.0 * e / max_ent)),
+            ),
+        );
+        let half = (n + 1) / 2;
+        let success = len_correct_prob == half
+            || len_correct_ent == half
+            || len_correct_ent_diff == Some(half)
+            || len_correct_prob_ent == half;
+        self.last_entropies = Some(entropies);
+
+        let count = n.max(1) as f64;
+        Ok(KeyMetrics {
+            avg_prob: probs.iter().sum::<f64>() / count,
+            avg_rank: ranks.iter().sum::<usize>() as f64 / count,
+            correct: rank_counts[0],
+            len_correct_prob: len_correct_prob,
+            len_correct_ent: len_correct_ent,
+            len_correct_ent_diff: len_correct_ent_diff,
+            len_correct_prob_ent: len_correct_prob_ent,
+            success: success,
+            max_rank: max_rank,
+            rank_counts: rank_counts,
+            log2_rank_lower_bound: ranks.iter().map(|r| ((r + 1) as f64).log2()).sum(),
+            log2_rank_bounds: log2_rank_bounds(&marginals, &self.key, self.bins),
+        })
+    }
+}
+
+//Indices sorted by ascending keys, equal keys keep their order
+fn order_by<I: Iterator<Item = f64>>(keys: I) -> Vec<usize> {
+    let keys: Vec<f64> = keys.collect();
+    let mut order: Vec<usize> = (0..keys.len()).collect();
+    order.sort_by(|i, j| {
+        keys[*i]
+            .partial_cmp(&keys[*j])
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    order
+}
+
+//Number of leading coefficients of order with rank 0 within the first half
+fn correct_prefix(ranks: &[usize], order: &[usize]) -> usize {
+    let half = (ranks.len() + 1) / 2;
+    order
+        .iter()
+        .take(half)
+        .take_while(|i| ranks[**i] == 0)
+        .count()
+}
@@ -1,20 +1,38 @@
 mod bin_tree;
 mod bin_var_node;
 mod check_graph;
+mod checkpoint;
 mod check_msg;
 mod check_node;
+mod decimation;
+mod evaluation;
+mod log_check_msg;
+mod rank_estimation;
+mod schedule;
+mod statistics;
 
 use crate::check_graph::{__pyo3_get_function_test_fft_2, __pyo3_get_function_test_fft_3};
 use pyo3::prelude::*;
 use pyo3::wrap_pyfunction;
 
 #[pymodule]
-fn check_bp(_: Python, m: &PyModule) -> PyResult<()> {
+fn check_bp(py: Python, m: &PyModule) -> PyResult<()> {
     m.add_class::<check_graph::CheckGraph>()?;
+    m.add("CheckGraphError", py.get_type::<check_graph::PyCheckGraphError>())?;
+    m.add("DimensionError", py.get_type::<check_graph::PyDimensionError>())?;
+    m.add("NodeNotFoundError", py.get_type::<check_graph::PyNodeNotFoundError>())?;
+    m.add(
+        "DegenerateDistributionError",
+        py.get_type::<check_graph::PyDegenerateDistributionError>(),
+    )?;
+    m.add_class::<statistics::IterationStats>()?;
+    m.add_class::<decimation::DecimationReport>()?;
+    m.add_class::<evaluation::KeyEvaluator>()?;
+    m.add_class::<evaluation::KeyMetrics>()?;
     m.add_class::<bin_tree::PyBinMultTreeInt>()?;
     m.add_class::<bin_tree::PyBinMultTreeList>()?;
-    m.add_function(wrap_pyfunction!(test_fft_2, m)?).unwrap();
-    m.add_function(wrap_pyfunction!(test_fft_3, m)?).unwrap();
+    m.add_function(wrap_pyfunction!(test_fft_2, m)?)?;
+    m.add_function(wrap_pyfunction!(test_fft_3, m)?)?;
 
     Ok(())
 }
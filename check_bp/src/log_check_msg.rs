@@ -0,0 +1,177 @@
+use crate::check_msg::{CheckMessage, DEGENERATE_DISTRIBUTION};
+use belief_propagation::{BPError, BPResult, Msg};
+use std::fmt::Debug;
+
+//Same as CheckMsg but stores ln(p), products of many messages become sums
+//and do not underflow
+//ETA is actually (eta-1)/2, for eta=2 use 5!
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LogCheckMsg<const ETA: usize> {
+    data: [f64; ETA],
+    //Entry handed out by get_mut, it holds p instead of ln(p) until the next
+    //call taking &mut self converts it back
+    linear: Option<usize>,
+}
+
+impl<const ETA: usize> LogCheckMsg<ETA> {
+    pub fn new() -> Self {
+        LogCheckMsg {
+            data: [f64::NEG_INFINITY; ETA],
+            linear: None,
+        }
+    }
+    //Gives access to ln(p) of value
+    pub fn get_log_mut(&mut self, value: i16) -> Option<&mut f64> {
+        self.sync();
+        Self::index(value).map(move |i| &mut self.data[i])
+    }
+    fn sync(&mut self) {
+        if let Some(i) = self.linear.take() {
+            self.data[i] = self.data[i].ln();
+        }
+    }
+    fn log(&self, i: usize) -> f64 {
+        if self.linear == Some(i) {
+            self.data[i].ln()
+        } else {
+            self.data[i]
+        }
+    }
+    fn logs(&self) -> [f64; ETA] {
+        let mut res = self.data;
+        if let Some(i) = self.linear {
+            res[i] = res[i].ln();
+        }
+        res
+    }
+    fn index(value: i16) -> Option<usize> {
+        let idx = value + (ETA / 2) as i16;
+        if idx < 0 || idx as usize >= ETA {
+            None
+        } else {
+            Some(idx as usize)
+        }
+    }
+    fn max(&self) -> f64 {
+        self.logs().iter().fold(f64::NEG_INFINITY, |m, l| m.max(*l))
+    }
+}
+
+impl<const ETA: usize> Msg<i16> for LogCheckMsg<ETA> {
+    fn new() -> Self {
+        LogCheckMsg::new()
+    }
+    fn get(&self, value: i16) -> Option<f64> {
+        Self::index(value).map(|i| self.log(i).exp())
+    }
+    //Use get_log_mut to access ln(p) directly
+    fn get_mut(&mut self, value: i16) -> Option<&mut f64> {
+        self.sync();
+        let i = Self::index(value)?;
+        self.data[i] = self.data[i].exp();
+        self.linear = Some(i);
+        Some(&mut self.data[i])
+    }
+    fn insert(&mut self, value: i16, p: f64) {
+        if let Some(l) = self.get_log_mut(value) {
+            *l = p.ln();
+        }
+    }
+    fn normalize(&mut self) -> BPResult<()> {
+        self.sync();
+        let max = self.max();
+        if !max.is_finite() || self.data.iter().any(|l| l.is_nan()) {
+            return Err(BPError::new(
+                "LogCheckMsg::Msg".to_owned(),
+                format!(
+                    "{}: did not find a useful value to normalize by",
+                    DEGENERATE_DISTRIBUTION
+                ),
+            )
+            .attach_debug_object("data (the log-distribution)", self.data.to_vec()));
+        }
+        self.data.iter_mut().for_each(|l| *l -= max);
+        Ok(())
+    }
+    fn is_valid(&self) -> bool {
+        self.logs().iter().all(|l| !l.is_nan())
+    }
+    fn mult_msg(&mut self, other: &Self) {
+        self.sync();
+        self.data
+            .iter_mut()
+            .zip(other.logs().iter())
+            .for_each(|(l_self, l_other)| *l_self += l_other);
+    }
+}
+
+impl<const ETA: usize> CheckMessage for LogCheckMsg<ETA> {
+    const SIZE: usize = ETA;
+    fn from_probabilities(probs: &[f64]) -> Self {
+        let mut msg = LogCheckMsg::new();
+        for (l, p) in msg.data.iter_mut().zip(probs.iter()) {
+            *l = p.ln();
+        }
+        msg
+    }
+    fn probabilities(&self) -> Vec<f64> {
+        let max = self.max();
+        if !max.is_finite() {
+            return vec![0.0; ETA];
+        }
+        self.logs().iter().map(|l| (l - max).exp()).collect()
+    }
+    fn fixed(value: i16) -> Self {
+        let mut msg = LogCheckMsg::new();
+        msg.insert(value, 1.0);
+        msg
+    }
+    fn product(&self, other: &Self) -> Self {
+        let mut res = *self;
+        res.mult_msg(other);
+        res
+    }
+    fn is_degenerate(&self) -> bool {
+        self.logs().iter().all(|l| *l == f64::NEG_INFINITY)
+    }
+    fn raw(&self) -> Vec<f64> {
+        self.logs().to_vec()
+    }
+    fn from_raw(raw: &[f64]) -> BPResult<Self> {
+        if raw.len() != ETA {
+            return Err(BPError::new(
+                "LogCheckMsg::from_raw".to_owned(),
+                format!("Wrong length {} of message (should be {}).", raw.len(), ETA),
+            ));
+        }
+        let mut msg = LogCheckMsg::new();
+        msg.data.copy_from_slice(raw);
+        Ok(msg)
+    }
+}
+
+//Iterates over (value, probability) with the probabilities scaled such that
+//the largest one is 1
+impl<const ETA: usize> IntoIterator for LogCheckMsg<ETA> {
+    type Item = (i16, f64);
+    type IntoIter = std::vec::IntoIter<(i16, f64)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        (&self).into_iter()
+    }
+}
+
+impl<'a, const ETA: usize> IntoIterator for &'a LogCheckMsg<ETA> {
+    type Item = (i16, f64);
+    type IntoIter = std::vec::IntoIter<(i16, f64)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let half = (ETA / 2) as i16;
+        self.probabilities()
+            .into_iter()
+            .enumerate()
+            .map(|(i, p)| (i as i16 - half, p))
+            .collect::<Vec<(i16, f64)>>()
+            .into_iter()
+    }
+}
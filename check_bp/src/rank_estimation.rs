@@ -0,0 +1,97 @@
+//Bounds on log2 of the rank of key (1 for the most likely key) among all
+//keys ordered by their probability, assuming independent marginals
+//Histograms of the costs log2(p_max/p) of all coefficients are convolved,
+//the costs are rounded down to multiples of a width such that the cost of
+//the key spans bins bins. The cost of a key is less than n widths above its
+//rounded one, which gives the bounds, only the first bins+n bins are kept
+pub fn log2_rank_bounds(marginals: &[Vec<f64>], key: &[i16], bins: usize) -> (f64, f64) {
+    let n = marginals.len();
+    let mut costs = Vec::with_capacity(n);
+    let mut key_costs = Vec::with_capacity(n);
+    let mut impossible = false;
+    for (marginal, value) in marginals.iter().zip(key.iter()) {
+        let max = marginal.iter().cloned().fold(0.0, f64::max);
+        //Finite for every p > 0, unlike log2(max/p) for subnormal p
+        let cost = |p: f64| (max.log2() - p.log2()).max(0.0);
+        let p = marginal[(value + (marginal.len() / 2) as i16) as usize];
+        if p > 0.0 {
+            key_costs.push(cost(p));
+        } else {
+            impossible = true;
+        }
+        costs.push(
+            marginal
+                .iter()
+                .filter(|p| **p > 0.0)
+                .map(|p| cost(*p))
+                .collect::<Vec<f64>>(),
+        );
+    }
+    if impossible {
+        //All keys of nonzero probability rank before the correct one
+        let possible = costs.iter().map(|c| (c.len() as f64).log2()).sum();
+        let all = marginals.iter().map(|m| (m.len() as f64).log2()).sum();
+        return (possible, all);
+    }
+    //If the key is the most likely one, only keys of the same cost count
+    let key_cost: f64 = key_costs.iter().sum();
+    let width = if key_cost > 0.0 {
+        key_cost / bins as f64
+    } else {
+        f64::MIN_POSITIVE
+    };
+    let len = bins + n;
+    let bin = |cost: f64| {
+        let b = (cost / width).floor();
+        if b < len as f64 {
+            Some(b as usize)
+        } else {
+            None
+        }
+    };
+    //Rounding down each cost keeps the sum at most bins
+    let key_bin = key_costs
+        .iter()
+        .map(|c| bin(*c).unwrap_or(bins))
+        .sum::<usize>()
+        .min(bins);
+    //Counts are scaled by 2^-scale to not overflow
+    let mut hist = vec![0.0; len];
+    hist[0] = 1.0;
+    let mut scale = 0.0;
+    for c in costs.iter() {
+        let mut next = vec![0.0; len];
+        for d in c.iter().filter_map(|c| bin(*c)) {
+            for (x, h) in next[d..].iter_mut().zip(hist.iter()) {
+                *x += h;
+            }
+        }
+        let max = next.iter().cloned().fold(0.0, f64::max);
+        if max > 0.0 {
+            next.iter_mut().for_each(|x| *x /= max);
+            scale += max.log2();
+        }
+        hist = next;
+    }
+    let log2_count = |hist: &[f64]| {
+        let sum: f64 = hist.iter().sum();
+        if sum > 0.0 {
+            sum.log2() + scale
+        } else {
+            f64::NEG_INFINITY
+        }
+    };
+    //Keys at least n bins below the correct one are more likely, keys in
+    //the first key_bin+n bins can be
+    let better = if key_bin >= n {
+        log2_count(&hist[..=key_bin - n])
+    } else {
+        f64::NEG_INFINITY
+    };
+    let lower = if better > 64.0 {
+        better
+    } else {
+        (better.exp2() + 1.0).log2()
+    };
+    (lower, log2_count(&hist[..key_bin + n]).max(lower))
+}
@@ -0,0 +1,120 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Schedule {
+    //All check nodes update in every step
+    Flooding,
+    //Check nodes are split into groups (by order of insertion), one group
+    //updates per step
+    Serial(usize),
+    //Only the given fraction of check nodes with the largest residual of the
+    //previous step send updated messages
+    Residual(f64),
+}
+
+//Shared by a graph and all its check nodes, changed by the graph between steps
+pub struct PropagationSettings {
+    //Stored as f64 bits
+    damping: AtomicU64,
+    //0 if not serial
+    groups: AtomicUsize,
+    step: AtomicUsize,
+    //Stored as f64 bits
+    residual_threshold: AtomicU64,
+    //Largest share of probability mass that wrapped around, as f64 bits
+    aliased_mass: AtomicU64,
+}
+
+impl PropagationSettings {
+    pub fn new() -> Self {
+        PropagationSettings {
+            damping: AtomicU64::new(0f64.to_bits()),
+            groups: AtomicUsize::new(0),
+            step: AtomicUsize::new(0),
+            residual_threshold: AtomicU64::new(0f64.to_bits()),
+            aliased_mass: AtomicU64::new(0f64.to_bits()),
+        }
+    }
+    pub fn damping(&self) -> f64 {
+        f64::from_bits(self.damping.load(Ordering::Relaxed))
+    }
+    pub fn set_damping(&self, damping: f64) {
+        self.damping.store(damping.to_bits(), Ordering::Relaxed);
+    }
+    pub fn set_schedule(&self, schedule: Schedule) {
+        let groups = match schedule {
+            Schedule::Serial(groups) => groups,
+            _ => 0,
+        };
+        self.groups.store(groups, Ordering::Relaxed);
+        self.set_residual_threshold(0.0);
+    }
+    pub fn set_step(&self, step: usize) {
+        self.step.store(step, Ordering::Relaxed);
+    }
+    pub fn residual_threshold(&self) -> f64 {
+        f64::from_bits(self.residual_threshold.load(Ordering::Relaxed))
+    }
+    pub fn set_residual_threshold(&self, threshold: f64) {
+        self.residual_threshold
+            .store(threshold.to_bits(), Ordering::Relaxed);
+    }
+    pub fn aliased_mass(&self) -> f64 {
+        f64::from_bits(self.aliased_mass.load(Ordering::Relaxed))
+    }
+    pub fn reset_aliased_mass(&self) {
+        self.aliased_mass.store(0f64.to_bits(), Ordering::Relaxed);
+    }
+    pub fn record_aliasing(&self, mass: f64) {
+        let _ = self
+            .aliased_mass
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+                if mass > f64::from_bits(bits) {
+                    Some(mass.to_bits())
+                } else {
+                    None
+                }
+            });
+    }
+    //Whether the check node inserted as order-th may update in the current step
+    pub fn is_active(&self, order: usize) -> bool {
+        let groups = self.groups.load(Ordering::Relaxed);
+        groups <= 1 || order % groups == self.step.load(Ordering::Relaxed) % groups
+    }
+}
+
+//Residual of a check node, written by the node and read by the graph
+pub struct Residual(AtomicU64);
+
+impl Residual {
+    pub fn new() -> Self {
+        Residual(AtomicU64::new(f64::INFINITY.to_bits()))
+    }
+    pub fn get(&self) -> f64 {
+        f64::from_bits(self.0.load(Ordering::Relaxed))
+    }
+    pub fn set(&self, residual: f64) {
+        self.0.store(residual.to_bits(), Ordering::Relaxed);
+    }
+}
+
+//What a check node needs to follow the schedule of its graph
+pub struct NodeSchedule {
+    pub settings: Arc<PropagationSettings>,
+    //Position among the check nodes of the graph
+    pub order: usize,
+    pub residual: Arc<Residual>,
+}
+
+//Smallest residual among the fraction of largest ones, nodes that did not
+//send anything yet (infinite residual) update anyway and are ignored
+pub fn residual_threshold(residuals: Vec<f64>, fraction: f64) -> f64 {
+    let mut residuals: Vec<f64> = residuals.into_iter().filter(|r| r.is_finite()).collect();
+    if residuals.is_empty() {
+        return 0.0;
+    }
+    residuals.sort_by(|r0, r1| r1.partial_cmp(r0).unwrap_or(std::cmp::Ordering::Equal));
+    let count = ((fraction * residuals.len() as f64).ceil() as usize).clamp(1, residuals.len());
+    residuals[count - 1]
+}
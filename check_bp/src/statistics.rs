@@ -0,0 +1,95 @@
+use pyo3::prelude::*;
+use pyo3::PyObjectProtocol;
+
+//Statistics of the variable marginals after one iteration (call of propagate),
+//the changes are None for the first recorded iteration
+#[pyclass]
+#[derive(Clone, Debug)]
+pub struct IterationStats {
+    #[pyo3(get)]
+    pub iteration: usize,
+    //Number of propagation steps done so far
+    #[pyo3(get)]
+    pub step: usize,
+    //Largest absolute change of a probability in any marginal
+    #[pyo3(get)]
+    pub max_change: Option<f64>,
+    //Largest absolute change per marginal, averaged over all variables
+    #[pyo3(get)]
+    pub mean_change: Option<f64>,
+    #[pyo3(get)]
+    pub mean_entropy: f64,
+    //Number of variables whose most likely value changed
+    #[pyo3(get)]
+    pub argmax_changes: Option<usize>,
+}
+
+#[pyproto]
+impl PyObjectProtocol for IterationStats {
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!(
+            "IterationStats(iteration={}, step={}, max_change={:?}, mean_change={:?}, mean_entropy={}, argmax_changes={:?})",
+            self.iteration,
+            self.step,
+            self.max_change,
+            self.mean_change,
+            self.mean_entropy,
+            self.argmax_changes
+        ))
+    }
+}
+
+//Index of the largest probability
+pub fn argmax(marginal: &Vec<f64>) -> usize {
+    marginal
+        .iter()
+        .enumerate()
+        .max_by(|(_, p0), (_, p1)| p0.partial_cmp(p1).unwrap_or(std::cmp::Ordering::Less))
+        .map(|(i, _)| i)
+        .unwrap_or(0)
+}
+
+//marginals[i] holds the probabilities of -eta..=eta of variable i
+pub fn iteration_stats(
+    iteration: usize,
+    step: usize,
+    last: Option<&Vec<Vec<f64>>>,
+    marginals: &Vec<Vec<f64>>,
+    entropies: &Vec<f64>,
+) -> IterationStats {
+    let count = marginals.len().max(1) as f64;
+    let mean_entropy = entropies.iter().sum::<f64>() / count;
+    let (max_change, mean_change, argmax_changes) = match last {
+        Some(last) => {
+            let changes: Vec<f64> = marginals
+                .iter()
+                .zip(last.iter())
+                .map(|(m, l)| {
+                    m.iter()
+                        .zip(l.iter())
+                        .map(|(p, q)| (p - q).abs())
+                        .fold(0.0, f64::max)
+                })
+                .collect();
+            let argmax_changes = marginals
+                .iter()
+                .zip(last.iter())
+                .filter(|(m, l)| argmax(m) != argmax(l))
+                .count();
+            (
+                Some(changes.iter().cloned().fold(0.0, f64::max)),
+                Some(changes.iter().sum::<f64>() / count),
+                Some(argmax_changes),
+            )
+        }
+        None => (None, None, None),
+    };
+    IterationStats {
+        iteration: iteration,
+        step: step,
+        max_change: max_change,
+        mean_change: mean_change,
+        mean_entropy: mean_entropy,
+        argmax_changes: argmax_changes,
+    }
+}
@@ -1,54 +1,53 @@
-from python_kyber import Poly, Polyvec
 from helpers import transpose
 from compress import compress_decompress
 
-def calc_delta_u(sample):
+def calc_delta_u(kyber, sample):
     r = sample.r.ntt()
-    u = r.apply_matrix_left_ntt(transpose(sample.pk.a)).intt() + sample.e1
+    u = r.apply_matrix_left_ntt(transpose(kyber, sample.pk.a)).intt() + sample.e1
     u = u.reduce()
     u_uncompressed = u
-    u = compress_decompress(u)
+    u = compress_decompress(kyber, u)
     deltau = u-u_uncompressed
     assert(sample.ct.b.to_lists() == u.to_lists())
     return deltau
 
-def calc_delta_v(sample):
+def calc_delta_v(kyber, sample):
     #Montg:
     #In: 0 0
     #Basemul: -1 -1 +1 -> -1
     #intt: +1 -> 0
-    v = Polyvec.scalar(sample.pk.pk, sample.r.ntt()).intt() + sample.e2
+    v = kyber.Polyvec.scalar(sample.pk.pk, sample.r.ntt()).intt() + sample.e2
     #Montg:
     #In: 0 0
     #intt: +1 -> +1 0
     #red: -1 -> 0 0
     #scalar_naiv: 0 0
-    v2 = Polyvec.scalar(sample.pk.pk, sample.r.ntt()).intt() + sample.e2
+    v2 = kyber.Polyvec.scalar(sample.pk.pk, sample.r.ntt()).intt() + sample.e2
     #assert(v2.reduce().to_list() == v.reduce().to_list())
-    v = v + Poly.from_msg(sample.nu)
+    v = v + kyber.Poly.from_msg(sample.nu)
     v = v.reduce()
     v_uncompressed = v
-    v = compress_decompress(v)
+    v = compress_decompress(kyber, v)
     deltav = v-v_uncompressed
     #assert(v.to_list() == sample.ct.v.to_list())
     return deltav
 
-def calculate_error_term_from_secret(sample):
-    delta_u = calc_delta_u(sample)
-    delta_v = calc_delta_v(sample)
-    er = Polyvec.scalar_naiv(sample.e, sample.r)
-    se1 = Polyvec.scalar_naiv((sample.e1+delta_u), sample.sk.sk.intt().montgomery_reduce())
+def calculate_error_term_from_secret(kyber, sample):
+    delta_u = calc_delta_u(kyber, sample)
+    delta_v = calc_delta_v(kyber, sample)
+    er = kyber.Polyvec.scalar_naiv(sample.e, sample.r)
+    se1 = kyber.Polyvec.scalar_naiv((sample.e1+delta_u), sample.sk.sk.intt().montgomery_reduce())
     res = er - se1 + sample.e2 + delta_v
     res = res.reduce()
     return res 
 
 
-def calculate_error_term_from_secret_naiv_0(sample):
+def calculate_error_term_from_secret_naiv_0(kyber, sample):
 
     sign = lambda i,j: 1 if (i+j) < 256 else -1
 
-    delta_u = calc_delta_u(sample).to_lists()
-    delta_v = calc_delta_v(sample).to_list()
+    delta_u = calc_delta_u(kyber, sample).to_lists()
+    delta_v = calc_delta_v(kyber, sample).to_list()
 
     elist = sample.e.to_lists()
     rlist = sample.r.to_lists()
@@ -66,10 +65,10 @@ def calculate_error_term_from_secret_naiv_0(sample):
     #res = res.reduce()
     return res
 
-def calculate_error_term_from_secret_naiv_0_1(sample):
+def calculate_error_term_from_secret_naiv_0_1(kyber, sample):
     coeff_index = 0
-    delta_u = calc_delta_u(sample)
-    delta_v = calc_delta_v(sample)
+    delta_u = calc_delta_u(kyber, sample)
+    delta_v = calc_delta_v(kyber, sample)
 
     s = [si.to_list() for si in sample.sk.sk.intt().montgomery_reduce().to_list()]
     sflat = [sik for si in s for sik in si]
@@ -96,9 +95,9 @@ def calculate_error_term_from_secret_naiv_0_1(sample):
 
     
 
-def calc_error_term(sample):
+def calc_error_term(kyber, sample):
     msg = sample.get_msg()
-    su = Polyvec.scalar(sample.sk.sk, sample.ct.b.ntt()).intt().reduce()
+    su = kyber.Polyvec.scalar(sample.sk.sk, sample.ct.b.ntt()).intt().reduce()
     r = sample.ct.v - su 
     r = r.reduce()
 #    assert(sample.nu == r.to_msg())
@@ -6,53 +6,31 @@ import numpy as np
 import enum
 
 from functools import lru_cache
-from multiprocessing import Pool
 
 
 from manipulate_ct import manipulate_coefficient
 from error_term import calc_error_term, calculate_error_term_from_secret, calc_delta_u, calc_delta_v, calculate_error_term_from_secret_naiv_0
 from flippedbits import count_flipped_bits
-from python_kyber import Poly, Polyvec, KyberSample, Ciphertext, PublicKey, SecretKey, KyberConstants
 
 
 q = 3329
 #dv = KyberConstants.DV()
 
-def create_matrix_threaded(thread_count, number=20000, tries=10, add_in_vec=True):
-    sample = KyberSample.generate(verify_decaps=True)
-    pool = Pool(thread_count)
-    chunck_size = 20
-    arg_count = number//chunck_size
-    key_tuple = (sample.pk.to_bytes(), sample.sk.to_bytes(), sample.e.to_lists())
-    args = [(key_tuple, th_no, chunck_size, tries, add_in_vec) for th_no in range(arg_count)]
-    left_overs = number - chunck_size * arg_count
-    if left_overs > 0:
-        args += [(key_tuple, arg_count+1, left_overs, tries, add_in_vec)]
-
-    #results = pool.map(create_matrix_tup, args)
-    results = []
-    total = 0
-    for _, res in enumerate(pool.imap_unordered(create_matrix_tup, args)):
-        results.append(res)
-        total += len(res[0]) + len(res[1])
-        print("{}/{}".format(total, number), end='\r')
-    print(" "*30, end='\r')
-    pool.close()
-    pool.join()
-
-    mat_le = [] 
-    mat_ge = []
-    vec_le = []
-    vec_ge = []
-    eq_le = []
-    eq_ge = []
-    for mat_ge_r, mat_le_r, vec_ge_r, vec_le_r, _, eq_ge_r, eq_le_r in results:
-        mat_le += mat_le_r
-        mat_ge += mat_ge_r
-        vec_le += vec_le_r
-        vec_ge += vec_ge_r
-        eq_le += eq_le_r
-        eq_ge += eq_ge_r
+def create_matrix_threaded(kyber, thread_count, number=20000, tries=10, add_in_vec=True, seed=None, coeff_indices=[0]):
+    #With a seed, keys and ciphertexts are reproducible
+    if seed is None:
+        sample = kyber.KyberSample.generate(verify_decaps=True)
+    else:
+        sample = kyber.KyberSample.generate_seeded(seed, verify_decaps=True)
+    generator = kyber.InequalityGenerator(sample, thread_count, add_in_vec)
+    generator.coeff_indices = coeff_indices
+    mat_ge, mat_le, vec_ge, vec_le, eq_ge, eq_le, _, _ = generator.generate(number, seed)
+    mat_ge = mat_ge.tolist()
+    mat_le = mat_le.tolist()
+    vec_ge = vec_ge.tolist()
+    vec_le = vec_le.tolist()
+    eq_ge = eq_ge.tolist()
+    eq_le = eq_le.tolist()
     print("Retrieved {} inequalities.".format(len(mat_le)+ len(mat_ge)))
     print("Checking inequalities..")
     key = check_inequalities(sample, mat_ge, mat_le, vec_ge, vec_le, None, add_in_vec, eq_ge, eq_le)
@@ -61,52 +39,16 @@ def create_matrix_threaded(thread_count, number=20000, tries=10, add_in_vec=True
     assert(len(mat_le) + len(mat_ge) == number)
     print("Passed checks.")
     
-    return mat_ge, mat_le, vec_ge, vec_le, key, eq_ge, eq_le
+    return mat_ge, mat_le, vec_ge, vec_le, key, eq_ge, eq_le, sample.pk
     
 
-def create_matrix_tup(tup):
-    return create_matrix(*tup)
-
-
-def create_matrix(key, th_no, number=20000, tries=10, add_in_vec=True):
-    mat_le = []
-    mat_ge = []
-    vec_le = []
-    vec_ge = []
-    eq_le = []
-    eq_ge = []
-    #######
-    pk = PublicKey.from_bytes(bytes(key[0]))
-    sk = SecretKey.from_bytes(bytes(key[1]))
-    e = Polyvec.new_from_list([Poly.from_list(p) for p in key[2]])
-    sample = KyberSample.generate_with_key(False, pk, sk, e)
-    #######
-    found = 0
-    #print(f"Chunck {th_no} started..")
-    while found < number:
-        found_one = False
-        ieqtype, row, b, eq = create_inequalities_from_sample(sample, add_in_vec=add_in_vec) 
-        if ieqtype == IneqType.LE:
-            mat_le.append(row)
-            vec_le.append(b)
-            eq_le.append(eq)
-            found += 1
-        elif ieqtype == IneqType.RE:
-            mat_ge.append(row)
-            vec_ge.append(b)
-            eq_ge.append(eq)
-            found += 1
-        sample = KyberSample.generate_with_key(False, sample.pk, sample.sk, sample.e) 
-    #print("Chunck {}: Found {} <= inequalities and {} >= inequalities".format(th_no, len(vec_le), len(vec_ge)))
-    return mat_ge, mat_le, vec_ge, vec_le, key, eq_ge, eq_le
-
-
-def calc_row(sample, coeff_index, delta_u, delta_v, add_in_vec):
-    sign = lambda i,j: 1 if ((i%256)+(j%256)) < 256 else -1
-    assert(coeff_index == 0)
-    e_list = [sign(i, 256-i)*sample.r.to_lists()[j][(256-i)%256] for j in range(KyberConstants.K()) for i in range(256)]
-    e1_list = [sign(i, 256-i)*sample.e1.to_lists()[j][(256-i)%256] for j in range(KyberConstants.K()) for i in range(256)]
-    du_list = [sign(i, 256-i)*delta_u.to_lists()[j][(256-i)%256] for j in range(KyberConstants.K()) for i in range(256)]
+def calc_row(kyber, sample, coeff_index, delta_u, delta_v, add_in_vec):
+    #Negacyclic rotation for coefficient coeff_index of the product
+    sign = lambda i: 1 if i <= coeff_index else -1
+    idx = lambda i: (coeff_index - i) % 256
+    e_list = [sign(i)*sample.r.to_lists()[j][idx(i)] for j in range(kyber.KyberConstants.K()) for i in range(256)]
+    e1_list = [sign(i)*sample.e1.to_lists()[j][idx(i)] for j in range(kyber.KyberConstants.K()) for i in range(256)]
+    du_list = [sign(i)*delta_u.to_lists()[j][idx(i)] for j in range(kyber.KyberConstants.K()) for i in range(256)]
     s_list = [-(duj+e1j) for duj, e1j in zip(e1_list, du_list)]
 
     add = 0
@@ -124,26 +66,25 @@ class IneqType(enum.Enum):
     RE = 1,
     NOT_FOUND = 2
 
-def create_inequalities_from_sample(sample, add_in_vec=True, max_v=10): 
+def create_inequalities_from_sample(kyber, sample, add_in_vec=True, max_v=10, coeff_index=0):
 
     msg = sample.get_msg()
 
     err = q//4
 
-    delta_u = calc_delta_u(sample)
-    delta_v = calc_delta_v(sample)
+    delta_u = calc_delta_u(kyber, sample)
+    delta_v = calc_delta_v(kyber, sample)
 
-    #for i in coefficients:
-    i = 0
+    i = coeff_index
     if abs(delta_v.to_list()[i]) >= max_v:
         return IneqType.NOT_FOUND, None, None, None
-    ct_manip = manipulate_coefficient(sample.ct, i, err)
+    ct_manip = manipulate_coefficient(kyber, sample.ct, i, err)
     flipped = count_flipped_bits(sample.ct, ct_manip)
     if flipped != 1:
         return IneqType.NOT_FOUND, None, None, None
     is_valid = sample.is_valid_ct(ct_manip)
-    row, b = calc_row(sample, i, delta_u, delta_v, add_in_vec) 
-    bit_is_0 = sample.nu[0] & 1 == 0
+    row, b = calc_row(kyber, sample, i, delta_u, delta_v, add_in_vec) 
+    bit_is_0 = (sample.nu[i//8] >> (i%8)) & 1 == 0
     if is_valid: #< or <=
         return IneqType.LE, row, b, bit_is_0
     else:
@@ -15,12 +15,13 @@ from zipfile import ZipFile
 from datetime import datetime
 
 from solver import create_graph
+from check_bp import CheckGraph, KeyEvaluator
 
 from test import test
 from test_check_bp import test_check_bp
 from test_bin_tree import test_bin_tree
 
-from python_kyber import KyberConstants
+import params
 
 from inequalities import create_matrix_threaded, key_from_file, mat_from_file, key_to_file, mat_to_file, check_inequalities_no_sample
 
@@ -45,6 +46,9 @@ def rank(res, val):
             rk += 1
     return rk
 
+def print_step(done, steps):
+    print("Step {}/{} done.".format(done, steps))
+
 def get_args():
     parser = argparse.ArgumentParser(description='CCA attack')
     parser.add_argument('--generate', action='store_true')
@@ -59,12 +63,36 @@ def get_args():
     parser.add_argument('--no-improve-abort', type=int, default=5)
     parser.add_argument('--results-file', type=str, default="results/results")
     parser.add_argument('--zip-file', type=str, default=None)
+    parser.add_argument('--priors', type=str, default=None, help='.npy file with one prior (probabilities of -eta..eta) per coefficient of e and s')
+    parser.add_argument('--flip-prob', type=float, default=0.0)
+    parser.add_argument('--damping', type=float, default=0.0, help='Mix new check node messages with the previous ones, in [0, 1)')
+    parser.add_argument('--schedule', type=str, default='flooding', choices=['flooding', 'serial', 'residual'])
+    parser.add_argument('--serial-groups', type=int, default=2, help='Number of check node groups updating in turns with --schedule serial')
+    parser.add_argument('--residual-fraction', type=float, default=0.1, help='Fraction of check nodes updating per step with --schedule residual')
+    parser.add_argument('--tolerance', type=float, default=None, help='Stop once no marginal changes by more than this between iterations')
+    parser.add_argument('--checkpoint', type=str, default=None, help='Save the graph including its messages to this file after every iteration')
+    parser.add_argument('--resume', type=str, default=None, help='Continue propagation from a checkpoint instead of building a new graph')
+    parser.add_argument('--fft-size', type=int, default=None, help='Fixed transform size of all check nodes (power of two), derived from the coefficients by default, the probability mass wrapping around in smaller transforms is reported')
+    parser.add_argument('--decimation-rounds', type=int, default=0, help='If the propagation fails, alternate propagating and fixing confident coefficients for this many rounds')
+    parser.add_argument('--fix-prob', type=float, default=0.95, help='Probability of the most likely value needed to fix a coefficient during decimation')
+    parser.add_argument('--recover-key', action='store_true', help='After every iteration, solve for the key from the most reliable coefficients and the public key')
+    parser.add_argument('--recovery-attempts', type=int, default=16, help='Number of candidate fixings tried per key recovery')
+    parser.add_argument('--enumeration-budget', type=int, default=0, help='If the propagation fails, check up to this many keys in decreasing probability against the public key')
+    parser.add_argument('--log-domain', action='store_true', help='Use log-domain messages (slower, but numerically stable for many inequalities)')
+    parser.add_argument('--coeff-indices', nargs='+', type=int, default=[0])
+    parser.add_argument('--parameter-set', type=str, default='Kyber512', choices=['Kyber512', 'Kyber768', 'Kyber1024'])
     args = parser.parse_args()
     if args.threads <= 0:
         args.threads = multiprocessing.cpu_count()
     if args.save and not args.generate:
         print("Incompatible arguments. Saving inequalities is only possible when generating.")
         exit(-1)
+    if any(i < 0 or i >= 256 for i in args.coeff_indices):
+        print("Coefficient indices have to be in [0, 255].")
+        exit(-1)
+    if args.flip_prob < 0 or args.flip_prob > 1:
+        print("Flip probability has to be in [0, 1].")
+        exit(-1)
     if args.runs <= 0:
         print("Cannot run <= 0 runs.")
         exit(-1)
@@ -88,15 +116,16 @@ def add_to_zip(filename, zip_filename, delete=False):
         print(f"Deleting {filename}..")
         os.remove(filename)
 
-def get_equations(number, filename, generate, save, th_no, zip_file, save_keys):
+def get_equations(kyber, number, filename, generate, save, th_no, zip_file, save_keys, seed=None, coeff_indices=[0]):
     if generate:
         print("Simulating faults on device to create inequalities..")
-        mat_ge, mat_le, vec_ge, vec_le, key, eq_ge, eq_le = create_matrix_threaded(th_no, number)
+        mat_ge, mat_le, vec_ge, vec_le, key, eq_ge, eq_le, pk = create_matrix_threaded(kyber, th_no, number, seed=seed, coeff_indices=coeff_indices)
         print("Found {} inequalities.".format(len(mat_ge) + len(mat_le)))
         if save or save_keys:
             print("\nSaving inequalities for {}.".format(filename))
             matfile = filename + ".txt"
             keyfile = filename + "_es.txt"
+            pkfile = filename + "_pk.bin"
             if save:
                 mat_to_file(mat_ge, ">", vec_ge, filename=matfile)
                 mat_to_file(mat_le, "<", vec_le, filename=matfile, mode='a')
@@ -105,11 +134,18 @@ def get_equations(number, filename, generate, save, th_no, zip_file, save_keys):
                 print("WARNING: Inequalities are not saved.")
             key_to_file(key, filename=keyfile)
             add_to_zip(keyfile, zip_file, delete=True)
+            with open(pkfile, 'wb') as f:
+                f.write(bytes(pk.to_bytes()))
+            add_to_zip(pkfile, zip_file, delete=True)
             print("")
     else:
         print("Loading inequalities from file/cache..")
         mat_ge, mat_le, vec_ge, vec_le = mat_from_file(filename + ".txt")
         key = key_from_file(filename + "_es.txt")
+        pk = None
+        if os.path.exists(filename + "_pk.bin"):
+            with open(filename + "_pk.bin", 'rb') as f:
+                pk = kyber.PublicKey.from_bytes(f.read())
         print("Loaded {} inequalities.".format(len(mat_ge) + len(mat_le)))
     noeqs = len(mat_ge) + len(mat_le)
     if noeqs  < number:
@@ -141,46 +177,17 @@ def get_equations(number, filename, generate, save, th_no, zip_file, save_keys):
     print("Checking inequalities..")
     assert(check_inequalities_no_sample(key, mat_ge, mat_le, vec_ge, vec_le))
     print("Continuing with {} inequalities.\n".format(noeqs))
-    return mat_ge, mat_le, vec_ge, vec_le, key, eq_ge, eq_le
-
-def count_correct_list(cr):
-    for i, val in enumerate(cr):
-        if not val:
-            return i
-    return len(cr)
-
-def check_abort_prob_ent(key, result_list):
-    max_ent = max(map(lambda r: r[2], result_list)) 
-    result_list = list(sorted(result_list, key=lambda r: r[1][1] - 2*r[2]/max_ent, reverse=True))
-    correct_list = [rk[0] == 0 for i, rk in enumerate(result_list) if i < len(key)/2] 
-    return all(correct_list), count_correct_list(correct_list), result_list
-
-def check_abort_prob(key, result_list):
-    result_list = list(sorted(result_list, key=lambda r: r[1][1], reverse=True))
-    correct_list = [rk[0] == 0 for i, rk in enumerate(result_list) if i < len(key)/2] 
-    return all(correct_list), count_correct_list(correct_list), result_list
+    return mat_ge, mat_le, vec_ge, vec_le, key, eq_ge, eq_le, pk
 
-def check_abort_ent(key, result_list):
-    result_list = list(sorted(result_list, key=lambda r: r[2]))
-    correct_list = [rk[0] == 0 for i, rk in enumerate(result_list) if i < len(key)/2] 
-    return all(correct_list), count_correct_list(correct_list), result_list
+def kyber_version(kyber):
+    return kyber.KyberConstants.K() *256
 
-def check_abort_ediff(key, result_list):
-    if result_list[0][3] == None:
-        return False, 0, []
-    result_list = list(sorted(result_list, key=lambda r: r[3]))
-    correct_list = [rk[0] == 0 for i, rk in enumerate(result_list) if i < len(key)/2] 
-    return all(correct_list), count_correct_list(correct_list), result_list
-
-def kyber_version():
-    return KyberConstants.K() *256
-
-def main():
-    args = get_args()
+def main(args):
+    kyber = params.parameter_set(args.parameter_set)
     seed = args.seed
     starttime = datetime.now()
     datestr = starttime.strftime("%m%d%Y%H%M")
-    ver = str(kyber_version())
+    ver = str(kyber_version(kyber))
     print("")
     print("Parameters: {}".format(str(args).split('(')[1].split(')')[0]))
     print("")
@@ -188,7 +195,7 @@ def main():
         results = []
         for i in range(args.runs):
             print("Experiment {} with seed {} and {} inequalities.\n".format(i, seed, number))
-            res = run(args, seed, number, i, args.runs, k, len(args.numbers), starttime, datestr)
+            res = run(kyber, args, seed, number, i, args.runs, k, len(args.numbers), starttime, datestr)
             results.append(res)
             seed = random.randint(0, 2**20)
             print("")
@@ -228,38 +235,36 @@ def print_progress(len_key, len_max, current_iteration, iterations, current_run,
     print("Total     : [{}{}] ({}%)".format(*form(prog_tot)))
     print("")
 
-def fix_coeffs(g, result_list):
-    i = 0
-    for r in result_list:
-        if r[1][1] < 0.95:
-            break
-        if r[1][0] in [-3, -2, 2, 3] and r[3] < 0.1:
-            if r[0] != 0:
-                print("WARNING: Fixing incorrect value")
-            g.set_fixed(r[4], r[1][0])
-            i += 1
-    print(f"Fixed {i} values.")
-
-
-def check_abort(key, result_list):
-    abortp, len_cor_p, _ = check_abort_prob(key, result_list)
-    aborte, len_cor_e, res_ent = check_abort_ent(key, result_list)
-    aborted, len_cor_ed, _ = check_abort_ediff(key, result_list)
-    abortpe, len_cor_pe, _ = check_abort_prob_ent(key, result_list)
-    print("Sorted ranks (Probability, Entropy, Entropy Delta, Entropy/Probability): {}, {}, {}, {}".format(len_cor_p, len_cor_e, len_cor_ed, len_cor_pe))
-    return (abortp or aborte or aborted or abortpe), len_cor_e, len_cor_ed, len_cor_p, len_cor_pe, res_ent
-
-def run(args, seed, number, current_run, runs, current_no, nonumbers, starttime, datestr):
+def run(kyber, args, seed, number, current_run, runs, current_no, nonumbers, starttime, datestr):
     random.seed(seed)
-    ver = str(kyber_version())
+    ver = str(kyber_version(kyber))
     run_file = args.file + '_' + ver + '_' + str(number) + '_' + str(current_run) + '_' + datestr
-    mat_ge, mat_le, vec_ge, vec_le, key, eq_ge, eq_le = get_equations(number, run_file, args.generate, args.save, args.threads, args.zip_file, args.save_keys)
-    g = create_graph(mat_ge, mat_le, vec_ge, vec_le, bino(eta=KyberConstants.ETA()), eq_ge, eq_le)
-    print("\nInitializing graph..")
-    g.ini()
+    mat_ge, mat_le, vec_ge, vec_le, key, eq_ge, eq_le, pk = get_equations(kyber, number, run_file, args.generate, args.save, args.threads, args.zip_file, args.save_keys, seed, args.coeff_indices)
+    dist = bino(eta=kyber.KyberConstants.ETA())
+    if args.priors is not None:
+        dist = np.load(args.priors)
+    if args.resume is not None:
+        print("Loading graph from {}..".format(args.resume))
+        g = CheckGraph.load(args.resume)
+    else:
+        g = create_graph(mat_ge, mat_le, vec_ge, vec_le, dist, eq_ge, eq_le, len(key), kyber.KyberConstants.ETA(), args.flip_prob, args.log_domain, args.fft_size, args.threads)
+        g.damping = args.damping
+        g.set_schedule(args.schedule, args.serial_groups, args.residual_fraction)
+        g.track_statistics = True
+        print("\nInitializing graph..")
+        g.ini()
     print("Beginning propagation with {} threads..\n".format(args.threads))
     success = False
-    result_list = []
+    evaluator = KeyEvaluator([int(k) for k in key])
+    rank_bounds = []
+    recovery = None
+    if args.recover_key:
+        if pk is None:
+            print("WARNING: No public key found, the key is not recovered.")
+        else:
+            recovery = kyber.KeyRecovery(pk)
+    recovered = False
+    enumerated = 0
     starttimestr = starttime.strftime("%m.%d.%Y %H:%M:%S")
     fixed = []
     fixed_incorrect = 0
@@ -267,50 +272,41 @@ def run(args, seed, number, current_run, runs, current_no, nonumbers, starttime,
     best_coeff_correct = 0
     for i in range(args.iterations):
         print("Propagating {}-th step..".format(i))
-        g.propagate(2, args.threads)
-        print("Fetching results..")
-        current_results_dict = g.get_results(args.threads)
-        print("Done fetching results.")
-        avgp = 0
-        avgrk = 0
-        results = []
-        results_last = result_list
-        result_list = []
-        correct = 0
-        for j, k in enumerate(key):
-            res, ent = current_results_dict[j]
-            prob = res[k]
-            avgp += prob
-            rk = rank(res, k)
-            avgrk += rk
-            results.append(res)
-            ent_diff = None
-            if len(results_last) > 0:
-                ent_diff = abs(ent-results[j][2]) 
-
-            result_list.append((rk,
-                                max(res.items(), key=lambda r: r[1]),
-                                ent,
-                                ent_diff,
-                                j))
-            if rk == 0:
-                correct += 1
-            #print("{}: {}, {}".format(k, prob, rk)) 
-        avgp /= KyberConstants.K()*512
-        avgrk /= KyberConstants.K()*512
+        g.propagate(2, args.threads, print_step)
+        stats = g.last_statistics
+        print(stats)
+        if g.fft_size is not None:
+            print("Largest share of probability mass wrapped around: {}".format(g.aliased_mass))
+        if args.checkpoint is not None:
+            g.save(args.checkpoint)
+        print("Evaluating results..")
+        metrics = evaluator.evaluate(g, args.threads)
+        avgp, avgrk, correct = metrics.avg_prob, metrics.avg_rank, metrics.correct
+        len_cor_p, len_cor_e, len_cor_pe = metrics.len_correct_prob, metrics.len_correct_ent, metrics.len_correct_prob_ent
+        len_cor_ed = metrics.len_correct_ent_diff or 0
         print("Done propagating.")
         print("Averages (Probability, Rank, Count Rank 0): {} {} {}".format(avgp, avgrk, correct))
-        abort_success, len_cor_p, len_cor_e, len_cor_ed, len_cor_pe, res_ent = check_abort(key, result_list)
-        #fix_coeffs(g, res_ent)
-        len_max = max(len_cor_e, len_cor_ed, len_cor_p, len_cor_pe) 
+        print("Sorted ranks (Probability, Entropy, Entropy Delta, Entropy/Probability): {}, {}, {}, {}".format(len_cor_p, len_cor_e, len_cor_ed, len_cor_pe))
+        rank_lower, rank_upper = metrics.log2_rank_bounds
+        rank_bounds.append([rank_lower, rank_upper])
+        print("Key rank between 2^{:.1f} and 2^{:.1f} (at least 2^{:.1f} by coefficient ranks)".format(rank_lower, rank_upper, metrics.log2_rank_lower_bound))
+        if recovery is not None:
+            print("Recovering key..")
+            recovered = recovery.recover(g.get_marginals(args.threads)[0], args.recovery_attempts) is not None
+            print("Recovered the key from the public key after {} candidates.".format(recovery.candidates) if recovered else "Key recovery failed.")
+        abort_success = metrics.success or recovered
+        len_max = metrics.len_correct_max
         best_coeff_correct = max(best_coeff_correct, len_max)
         print_progress(len(key), len_max, i+1, args.iterations, current_run, runs, current_no, nonumbers)
         print(f"Maximal correct coefficients in this run: {best_coeff_correct}")
         runtime = datetime.now() - starttime 
-        abort_success = abort_success or (fixed_incorrect == 0 and len(fixed) >= 512)
+        abort_success = abort_success or (fixed_incorrect == 0 and len(fixed) >= len(key) // 2)
         if abort_success:
             success = True
             break
+        if args.tolerance is not None and stats.max_change is not None and stats.max_change <= args.tolerance:
+            print("Converged (maximal change {}). Aborting.".format(stats.max_change))
+            break
         abort_fail = False
         #Check fail conditions
         if last_improved['max_coeffs_correct'] < len_max:
@@ -324,6 +320,30 @@ def run(args, seed, number, current_run, runs, current_no, nonumbers, starttime,
         if abort_fail:
             success = False
             break
+    if not success and args.decimation_rounds > 0:
+        print("Decimating..")
+        report = g.decimate(args.decimation_rounds, 2, args.threads, args.fix_prob, callback=print_step)
+        print(report)
+        fixed = report.values
+        fixed_incorrect = sum([1 for j, v in fixed.items() if key[j] != v])
+        print("Fixed {} coefficients, {} incorrectly.".format(len(fixed), fixed_incorrect))
+        metrics = evaluator.evaluate(g, args.threads)
+        print(metrics)
+        avgp, avgrk, correct = metrics.avg_prob, metrics.avg_rank, metrics.correct
+        if recovery is not None:
+            recovered = recovery.recover(g.get_marginals(args.threads)[0], args.recovery_attempts) is not None
+            print("Recovered the key from the public key." if recovered else "Key recovery failed.")
+        success = metrics.success or recovered or (fixed_incorrect == 0 and len(fixed) >= len(key) // 2)
+    if not success and args.enumeration_budget > 0:
+        if pk is None:
+            print("WARNING: No public key found, keys are not enumerated.")
+        else:
+            print("Enumerating keys..")
+            enumeration = kyber.KeyEnumeration(pk, args.threads)
+            found = enumeration.enumerate(g.get_marginals(args.threads)[0], args.enumeration_budget)
+            enumerated = enumeration.candidates
+            print("Found the key after {} candidates.".format(enumerated) if found is not None else "Key not found in {} candidates.".format(enumerated))
+            success = found is not None
     runtime = datetime.now() - starttime 
     if success:
         print("Succeeded with {} inequalities in {} iterations ({} minutes).".format(number, i+1, runtime.total_seconds()//60))
@@ -343,10 +363,14 @@ def run(args, seed, number, current_run, runs, current_no, nonumbers, starttime,
             'len_correct_prob_ent': len_cor_pe,
             'ineqs': run_file,
             'parameter_set': ver,
-            'best_coeff_correct': best_coeff_correct}
+            'best_coeff_correct': best_coeff_correct,
+            'recovered': recovered,
+            'enumerated': enumerated,
+            'log2_rank_bounds': rank_bounds}
 
 if __name__ == '__main__':
+    args = get_args()
     test_bin_tree()
    # test_check_bp()
-    test()
-    main()
+    test(params.parameter_set(args.parameter_set))
+    main(args)
@@ -0,0 +1,6 @@
+import python_kyber
+
+#Parameter sets are modules of python_kyber (e.g. python_kyber.Kyber768),
+#functions using one take it as their kyber argument
+def parameter_set(name='Kyber512'):
+    return getattr(python_kyber, name)
@@ -1,20 +1,24 @@
 
+import numpy as np
 from check_bp import CheckGraph
 
-def create_graph(mat_ge, mat_le, vec_ge, vec_le, dist, is_equals_le, is_equals_ge):
+def create_graph(mat_ge, mat_le, vec_ge, vec_le, dist, is_equals_le, is_equals_ge, var_count, eta, flip_prob=0.0, log_domain=False, fft_size=None, threads=1):
     print("Building check graph..")
-    g = CheckGraph()
-    g.add_var_nodes(dist) 
-    lineno = 0
-    maxv = 0
-    for row, val, eq in zip(mat_ge, vec_ge, is_equals_ge):
-        maxv = max(maxv, max(row))  
-        g.add_equation("Line {}".format(lineno), row, val, False, eq)
-        lineno += 1
-    for row, val, eq in zip(mat_le, vec_le, is_equals_le):
-        maxv = max(maxv, max(row))  
-        g.add_equation("Line {}".format(lineno), row, val, True, eq)
-        lineno += 1
+    g = CheckGraph(var_count, eta, log_domain)
+    #Has to be set before adding equations
+    g.fft_size = fft_size
+    #dist is either one prior for all variables or a list with one row per variable
+    if isinstance(dist, dict):
+        g.add_var_nodes(dist)
+    else:
+        g.add_var_nodes_with_priors([list(row) for row in dist])
+    #All inequalities are added at once, the >= ones first
+    mat = np.concatenate([np.asarray(mat_ge, dtype=np.int16).reshape(-1, var_count), np.asarray(mat_le, dtype=np.int16).reshape(-1, var_count)])
+    vec = np.concatenate([np.asarray(vec_ge, dtype=np.int16), np.asarray(vec_le, dtype=np.int16)])
+    is_smaller = np.concatenate([np.zeros(len(vec_ge), dtype=bool), np.ones(len(vec_le), dtype=bool)])
+    is_equal = np.concatenate([np.asarray(is_equals_ge, dtype=bool).reshape(-1), np.asarray(is_equals_le, dtype=bool).reshape(-1)])
+    g.add_equations(mat, vec, is_smaller, is_equal, flip_prob, threads)
+    maxv = np.max(mat) if mat.size > 0 else 0
     print("Maximal value in equations: ", maxv)
     return g
 
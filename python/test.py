@@ -1,85 +1,90 @@
-from python_kyber import Poly, Polyvec, KyberSample, Ciphertext, PublicKey, SecretKey, KyberConstants
+import numpy as np
+import params
 from manipulate_ct import manipulate_coefficient
 from compress import compress_decompress
 from error_term import calculate_error_term_from_secret, calc_error_term, calculate_error_term_from_secret_naiv_0, calculate_error_term_from_secret_naiv_0_1
 from helpers import transpose
-
-def test():
-    sample = KyberSample.generate(True)
-    test_consistancy(sample)
-    test_manipulation(sample)
-    test_intt(sample)
-    test_naiv_mult(sample)
-    test_error_term(sample)
-    test_vec_mult(sample)
-
-def test_naiv_mult(sample):
+from inequalities import create_inequalities_from_sample, IneqType
+
+def test(kyber=params.parameter_set()):
+    sample = kyber.KyberSample.generate(True)
+    test_consistancy(kyber, sample)
+    test_manipulation(kyber, sample)
+    test_intt(kyber, sample)
+    test_naiv_mult(kyber, sample)
+    test_error_term(kyber, sample)
+    test_vec_mult(kyber, sample)
+    test_inequality_generator(kyber, sample)
+    test_key_recovery(kyber, sample)
+    test_key_enumeration(kyber, sample)
+
+def test_naiv_mult(kyber, sample):
     x0 = (sample.e2 * sample.e2).reduce()
-    x1 = Poly.mul_ntt(sample.e2.ntt(), sample.e2.ntt()).intt().reduce()
+    x1 = kyber.Poly.mul_ntt(sample.e2.ntt(), sample.e2.ntt()).intt().reduce()
     assert(x0.to_list() == x1.to_list())
 
-    r0 = Polyvec.scalar(sample.e.ntt(), sample.e.ntt()).intt().reduce()
-    r1 = Polyvec.scalar_naiv(sample.e, sample.e).reduce()
+    r0 = kyber.Polyvec.scalar(sample.e.ntt(), sample.e.ntt()).intt().reduce()
+    r1 = kyber.Polyvec.scalar_naiv(sample.e, sample.e).reduce()
     assert(r0.to_list() == r1.to_list())
     
 
-def test_consistancy(sample):
-    v = Polyvec.scalar(sample.pk.pk, sample.r.ntt()).intt() + sample.e2
-    v = v + Poly.from_msg(sample.nu)
+def test_consistancy(kyber, sample):
+    v = kyber.Polyvec.scalar(sample.pk.pk, sample.r.ntt()).intt() + sample.e2
+    v = v + kyber.Poly.from_msg(sample.nu)
     v = v.reduce()
-    v_uncompressed = Poly.from_bytes_uncompressed(v.to_bytes_uncompressed()).reduce()
+    v_uncompressed = kyber.Poly.from_bytes_uncompressed(v.to_bytes_uncompressed()).reduce()
     assert(v.to_list() == v_uncompressed.to_list())
     v3 = v
-    v = compress_decompress(v)
+    v = compress_decompress(kyber, v)
     #decomp(compress(v)) = v+delta_v
     deltav = v-v3
     assert((v3+deltav).to_list() == v.to_list())
     assert(v.to_list() == sample.ct.v.to_list())
     r = sample.r.ntt()
-    u = r.apply_matrix_left_ntt(transpose(sample.pk.a)).intt() + sample.e1
+    u = r.apply_matrix_left_ntt(transpose(kyber, sample.pk.a)).intt() + sample.e1
     u = u.reduce()
     u_uncompressed = u
-    u = compress_decompress(u)
+    u = compress_decompress(kyber, u)
     deltau = u-u_uncompressed
     assert((deltau+u_uncompressed).to_lists() == u.to_lists())
     assert(sample.ct.b.to_lists() == u.to_lists())
 
-    su = Polyvec.scalar(u.ntt(), sample.sk.sk).intt()
-    assert(Poly.from_msg(sample.nu).to_msg() == sample.nu)
+    su = kyber.Polyvec.scalar(u.ntt(), sample.sk.sk).intt()
+    assert(kyber.Poly.from_msg(sample.nu).to_msg() == sample.nu)
 
-    assert(((Polyvec.scalar(sample.pk.pk, sample.r.ntt()).intt() + sample.e2).reduce() + deltav).reduce().to_list() == (v-Poly.from_msg(sample.nu)).reduce().to_list())
-    assert(((Polyvec.scalar(sample.pk.pk, sample.r.ntt()).intt() + sample.e2).reduce() + deltav + Poly.from_msg(sample.nu)).reduce().to_list() == v.reduce().to_list())
+    assert(((kyber.Polyvec.scalar(sample.pk.pk, sample.r.ntt()).intt() + sample.e2).reduce() + deltav).reduce().to_list() == (v-kyber.Poly.from_msg(sample.nu)).reduce().to_list())
+    assert(((kyber.Polyvec.scalar(sample.pk.pk, sample.r.ntt()).intt() + sample.e2).reduce() + deltav + kyber.Poly.from_msg(sample.nu)).reduce().to_list() == v.reduce().to_list())
     
-    su_uncompressed = Polyvec.scalar(sample.sk.sk, u_uncompressed.ntt()).intt()
-    u_uncompressed_2 = sample.r.ntt().apply_matrix_left_ntt(transpose(sample.pk.a)).intt() + sample.e1
+    su_uncompressed = kyber.Polyvec.scalar(sample.sk.sk, u_uncompressed.ntt()).intt()
+    u_uncompressed_2 = sample.r.ntt().apply_matrix_left_ntt(transpose(kyber, sample.pk.a)).intt() + sample.e1
     u_uncompressed_2 = u_uncompressed_2.reduce()
     assert(u_uncompressed.to_lists() == u_uncompressed_2.to_lists())
     assert((u_uncompressed_2+deltau).to_lists() == u.to_lists())
-    su_uncompressed_2 = Polyvec.scalar(sample.sk.sk, u.ntt()).intt() - Polyvec.scalar(sample.sk.sk, deltau.ntt()).intt()
+    su_uncompressed_2 = kyber.Polyvec.scalar(sample.sk.sk, u.ntt()).intt() - kyber.Polyvec.scalar(sample.sk.sk, deltau.ntt()).intt()
     su_uncompressed_2 = su_uncompressed_2.reduce()
     su_uncompressed = su_uncompressed.reduce()
     assert(su_uncompressed_2.to_list() == su_uncompressed.to_list())
      
-    er_minus_se1_plus_e2_compressed = Polyvec.scalar(sample.e.ntt(), sample.r.ntt()).intt() - Polyvec.scalar(sample.sk.sk, (sample.e1 + deltau).ntt()).intt() + sample.e2 + deltav
+    er_minus_se1_plus_e2_compressed = kyber.Polyvec.scalar(sample.e.ntt(), sample.r.ntt()).intt() - kyber.Polyvec.scalar(sample.sk.sk, (sample.e1 + deltau).ntt()).intt() + sample.e2 + deltav
     v_minus_su_compressed = (v - su).reduce()
 
-    assert(er_minus_se1_plus_e2_compressed.reduce().to_list() == (v_minus_su_compressed - Poly.from_msg(sample.nu)).reduce().to_list())
+    assert(er_minus_se1_plus_e2_compressed.reduce().to_list() == (v_minus_su_compressed - kyber.Poly.from_msg(sample.nu)).reduce().to_list())
     
     
-def test_error_term(sample): 
-    err_term_from_secret = calculate_error_term_from_secret(sample).to_list()
-    err_term_from_secret_naiv_0 = calculate_error_term_from_secret_naiv_0(sample)
-    error_term = calc_error_term(sample).to_list()
-    err_term_from_secret_naiv_0_1 = calculate_error_term_from_secret_naiv_0_1(sample)
+def test_error_term(kyber, sample): 
+    err_term_from_secret = calculate_error_term_from_secret(kyber, sample).to_list()
+    err_term_from_secret_naiv_0 = calculate_error_term_from_secret_naiv_0(kyber, sample)
+    error_term = calc_error_term(kyber, sample).to_list()
+    err_term_from_secret_naiv_0_1 = calculate_error_term_from_secret_naiv_0_1(kyber, sample)
     assert(err_term_from_secret == error_term)
     assert(err_term_from_secret[0] % 3329 == err_term_from_secret_naiv_0 % 3329)  
     assert(err_term_from_secret_naiv_0_1 == err_term_from_secret_naiv_0 % 3329)
 
-def test_intt(sample):
+def test_intt(kyber, sample):
     assert(sample.e2.ntt().intt().montgomery_reduce().to_list() == sample.e2.to_list())
     assert(sample.e.ntt().intt().montgomery_reduce().to_lists() == sample.e.to_lists())
 
-def test_vec_mult(sample): 
+def test_vec_mult(kyber, sample): 
     e2list = sample.e2.to_list()
 
     sign = lambda i,j: 1 if (i+j) < 256 else -1
@@ -96,29 +101,93 @@ def test_vec_mult(sample):
     assert(e2_squared[0] % 3329 == e2_squared_naiv_0)
 
     ##
-    r_list = [sign(i%256, k)*sample.r.to_lists()[j][i%256] for j in range(KyberConstants.K()) for i, k in zip(reversed(range(1, 257)), range(256))]
-    e_list = [sample.e.to_lists()[j][i] for j in range(KyberConstants.K()) for i in range(0, 256)]
+    r_list = [sign(i%256, k)*sample.r.to_lists()[j][i%256] for j in range(kyber.KyberConstants.K()) for i, k in zip(reversed(range(1, 257)), range(256))]
+    e_list = [sample.e.to_lists()[j][i] for j in range(kyber.KyberConstants.K()) for i in range(0, 256)]
     comp = sum([(ri*ei) % 3329 for ri, ei in zip(r_list, e_list)]) % 3329
 
-    re = (Polyvec.scalar_naiv(sample.r, sample.e)).reduce().to_list()
+    re = (kyber.Polyvec.scalar_naiv(sample.r, sample.e)).reduce().to_list()
 
-    re_naiv = sum([sign(i,(256-i)%256)*sample.e.to_lists()[j][i]*sample.r.to_lists()[j][(256-i)%256] for j in range(KyberConstants.K()) for i in range(256)]) % 3329
+    re_naiv = sum([sign(i,(256-i)%256)*sample.e.to_lists()[j][i]*sample.r.to_lists()[j][(256-i)%256] for j in range(kyber.KyberConstants.K()) for i in range(256)]) % 3329
     assert(comp == re[0]%3329)
  
     
 
-def test_manipulation(sample):
+def test_manipulation(kyber, sample):
     ctbytes = sample.ct.to_bytes_list()
-    ct2 = Ciphertext.from_bytes_list(ctbytes)
+    ct2 = kyber.Ciphertext.from_bytes_list(ctbytes)
     assert(sample.is_valid_ct(ct2))
-    ct3 = Ciphertext(sample.ct.b, sample.ct.v)
+    ct3 = kyber.Ciphertext(sample.ct.b, sample.ct.v)
     assert(sample.is_valid_ct(ct3))
     v_manip = ct3.v.to_list()
     v_manip[0] += 1243
     v_manip[0] %= 3329
-    ct4 = Ciphertext(sample.ct.b, Poly.from_list(v_manip))
+    ct4 = kyber.Ciphertext(sample.ct.b, kyber.Poly.from_list(v_manip))
     assert(not sample.is_valid_ct(ct4))
-    ct_manip = manipulate_coefficient(sample.ct, 0, 0)
+    ct_manip = manipulate_coefficient(kyber, sample.ct, 0, 0)
     assert(sample.is_valid_ct(ct_manip))
-    ct_manip = manipulate_coefficient(sample.ct, 0, 1000)
+    ct_manip = manipulate_coefficient(kyber, sample.ct, 0, 1000)
     assert(not sample.is_valid_ct(ct_manip)) 
+
+def test_inequality_generator(kyber, sample):
+    #Compare the native generator with the python reference implementation
+    for k in range(20):
+        coeff_index = [0, 1, 100, 255][k % 4]
+        sample = kyber.KyberSample.generate_with_key(False, sample.pk, sample.sk, sample.e)
+        ieqtype, row, b, eq = create_inequalities_from_sample(kyber, sample, coeff_index=coeff_index)
+        res = kyber.InequalityGenerator.from_sample(sample, True, 10, coeff_index)
+        if ieqtype == IneqType.NOT_FOUND:
+            assert(res is None)
+            continue
+        is_le, row_rs, b_rs, eq_rs = res
+        assert(is_le == (ieqtype == IneqType.LE))
+        assert(row == row_rs)
+        assert(b == b_rs)
+        assert(eq == eq_rs)
+    #The inequalities of a seed do not depend on the number of threads
+    res1 = kyber.InequalityGenerator(sample, 1, True).generate(200, 7)
+    generator = kyber.InequalityGenerator(sample, 1, True)
+    generator.threads = 3
+    res3 = generator.generate(200, 7)
+    assert(all((x == y).all() for x, y in zip(res1, res3)))
+    try:
+        generator.threads = 0
+        assert(False)
+    except ValueError:
+        pass
+
+def test_key_recovery(kyber, sample):
+    q = kyber.KyberConstants.Q()
+    eta = kyber.KyberConstants.ETA()
+    s = [(c + q//2) % q - q//2 for p in sample.sk.sk.intt().montgomery_reduce().to_lists() for c in p]
+    key = [c for p in sample.e.to_lists() for c in p] + s
+    #Three of five coefficients are confidently correct, the others have a wrong most likely value
+    marginals = np.full((len(key), 2*eta + 1), 0.01)
+    for i, k in enumerate(key):
+        if i % 5 < 3:
+            marginals[i, k + eta] = 0.9
+        else:
+            marginals[i, (k + eta + 1) % (2*eta + 1)] = 0.5
+    recovery = kyber.KeyRecovery(sample.pk)
+    sk = recovery.recover(marginals)
+    assert(sk is not None)
+    assert(sk.to_bytes_indcpa() == sample.sk.to_bytes_indcpa())
+    assert(recovery.verify(key) is not None)
+    key[0] = key[0] + 1 if key[0] < eta else key[0] - 1
+    assert(recovery.verify(key) is None)
+
+def test_key_enumeration(kyber, sample):
+    q = kyber.KyberConstants.Q()
+    eta = kyber.KyberConstants.ETA()
+    s = [(c + q//2) % q - q//2 for p in sample.sk.sk.intt().montgomery_reduce().to_lists() for c in p]
+    key = [c for p in sample.e.to_lists() for c in p] + s
+    #All coefficients but three (in e and s) are certain, for those the correct value is the second most likely
+    marginals = np.full((len(key), 2*eta + 1), 0.01)
+    uncertain = [0, len(key)//2 + 17, len(key) - 1]
+    for i, k in enumerate(key):
+        marginals[i, k + eta] = 0.45 if i in uncertain else 0.9
+        if i in uncertain:
+            marginals[i, (k + eta + 1) % (2*eta + 1)] = 0.5
+    enumeration = kyber.KeyEnumeration(sample.pk, 2)
+    assert(enumeration.enumerate(marginals, 1000) == key)
+    assert(enumeration.candidates == 8)
+    assert(enumeration.enumerate(marginals, 4) is None)
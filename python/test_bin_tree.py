@@ -23,8 +23,8 @@ def test_int_tree_no_two_power():
             assert(v == 1)
 
 
-def test_int_tree_random():
-    l = [random.randint(-1, 1) for _ in range(10)]
+def test_int_tree_random(length=10):
+    l = [random.randint(-1, 1) for _ in range(length)]
     for i, v in enumerate(l):
         if v == 0:
             l[i] = 1
@@ -57,6 +57,7 @@ def test_bin_tree():
     test_int_tree_no_two_power()
     for _ in range(10):
         test_int_tree_random()
+        test_int_tree_random(random.randint(2, 20))
         test_list_tree()
 
 
@@ -1,9 +1,9 @@
 import numpy as np
 import random
-from check_bp import CheckGraph, test_fft_2, test_fft_3
+from check_bp import CheckGraph, KeyEvaluator, CheckGraphError, DimensionError, NodeNotFoundError, test_fft_2, test_fft_3
 
 def test_check_bp_le():
-    g = CheckGraph()
+    g = CheckGraph(2048, 2)
     g.add_var_nodes({0: 0.5, 1: 0.5}) 
     for i in range(5): 
         g.add_equation(str(i), [1 if j == i else 0 for j in range(2048)], 0, True, False)
@@ -21,7 +21,7 @@ def test_check_bp_le():
     print(g.get_result(100))
 
 def test_check_bp_ge():
-    g = CheckGraph()
+    g = CheckGraph(2048, 2)
     g.add_var_nodes({0: 0.5, -1: 0.5}) 
     for i in range(5): 
         g.add_equation(str(i), [1 if j == i else 0 for j in range(2048)], 0, False, False)
@@ -40,7 +40,7 @@ def test_check_bp_ge():
     print(g.get_result(100))
 
 def test_check_bp_2():
-    g = CheckGraph()
+    g = CheckGraph(2048, 2)
     g.add_var_nodes({-1: 0.3, 0: 0.4, 1: 0.3}) 
     for i in range(5): 
         g.add_equation(str(i), [2 if j == i else 0 for j in range(2048)], 1, True, False)
@@ -63,7 +63,7 @@ def test_check_bp_2():
     print(g.get_result(100))
 
 def test_check_bp_3():
-    g = CheckGraph()
+    g = CheckGraph(2048, 2)
     g.add_var_nodes({-1: 0, 0: 0.5, 1: 0.5}) 
     for i in range(0, 6, 2): 
         g.add_equation(str(i), [1 if j == i or j == i+1 else 0 for j in range(2048)], 1, True, False)
@@ -84,8 +84,392 @@ def test_check_bp_3():
     print("At 100:")
     print(g.get_result(100))
 
-def test_random_equations(key_len=1024, eq_count=8000, max_err=20):
-    g = CheckGraph()
+def test_check_bp_sparse():
+    g = CheckGraph(4, 2)
+    g.add_var_nodes({0: 0.5, 1: 0.5})
+    #The zero coefficient is dropped, leaving three inputs
+    g.add_equation_sparse("0", [2, 0, 1, 3], [1, 1, 1, 0], 0, True, True)
+    #Single input
+    g.add_equation_sparse("1", [3], [1], 0, False, False)
+    g.ini()
+    g.propagate(2, 1)
+    for i in range(4):
+        res = g.get_result(i)
+        expected = 1 if i == 3 else 0
+        for v, p in res.items():
+            if v == expected:
+                assert(abs(p - 1) <= 0.000001)
+            else:
+                assert(abs(p) <= 0.0000001)
+
+def test_check_bp_single_connection():
+    #Every variable appears in a single sparse equation
+    g = CheckGraph(3, 2)
+    g.add_var_nodes({-1: 0.25, 0: 0.5, 1: 0.25})
+    #x0 + x1 > 0 and x2 >= 0
+    g.add_equation_sparse("0", [0, 1], [1, 1], 0, False, False)
+    g.add_equation_sparse("1", [2], [1], 0, False, True)
+    g.ini()
+    g.propagate(2, 1)
+    res = g.get_result(0)
+    assert(abs(res[1] - 0.6) <= 0.000001 and abs(res[0] - 0.4) <= 0.000001)
+    res = g.get_result(2)
+    assert(abs(res[0] - 2/3) <= 0.000001 and abs(res[1] - 1/3) <= 0.000001)
+    assert(res[-1] <= 0.0000001)
+
+def test_check_bp_noisy():
+    g = CheckGraph(2, 2)
+    g.add_var_nodes({0: 0.5, 1: 0.5})
+    for i in range(3):
+        g.add_equation_sparse(str(i), [0], [1], 0, True, True, 0.1)
+    #Wrong answer of the oracle
+    g.add_equation_sparse("3", [0], [1], 0, False, False, 0.1)
+    g.add_equation_sparse("4", [1], [1], 0, True, True)
+    g.ini()
+    g.propagate(2, 1)
+    res = g.get_result(0)
+    assert(res[0] > 0.9)
+    assert(res[1] > 0.001)
+
+def test_check_bp_equalities():
+    g = CheckGraph(3, 2)
+    g.add_var_nodes({-1: 1/3, 0: 1/3, 1: 1/3})
+    g.add_equality("0", [1, 1], 2, indices=[0, 1])
+    g.add_modular_equation("1", [0, 0, 2], 0, 4)
+    g.add_interval("2", [0, 1, 1], 1, 2)
+    g.ini()
+    g.propagate(2, 1)
+    for i, expected in enumerate([1, 1, 0]):
+        res = g.get_result(i)
+        for v, p in res.items():
+            if v == expected:
+                assert(abs(p - 1) <= 0.000001)
+            else:
+                assert(abs(p) <= 0.0000001)
+
+def test_check_bp_priors():
+    g = CheckGraph(2, 2)
+    g.add_var_nodes_with_priors([[0, 0, 0.5, 0.5, 0], [0, 0.5, 0.5, 0, 0]])
+    g.add_equation("0", [1, 1], 0, False, False)
+    g.ini()
+    g.propagate(2, 1)
+    assert(abs(g.get_result(0)[1] - 1) <= 0.000001)
+    assert(abs(g.get_result(1)[0] - 1) <= 0.000001)
+    g = CheckGraph(2, 2)
+    try:
+        g.add_var_nodes_with_priors([[0.2]*5, [0.25]*4])
+        assert(False)
+    except DimensionError:
+        pass
+    #Both bulk APIs add all variable nodes only once
+    for add in [lambda g: g.add_var_nodes({0: 0.5, 1: 0.5}), lambda g: g.add_var_nodes_with_priors([[0.2]*5]*2)]:
+        g = CheckGraph(2, 2)
+        add(g)
+        try:
+            add(g)
+            assert(False)
+        except CheckGraphError:
+            pass
+
+def test_check_bp_add_equations():
+    mat = np.array([[1, 1, 0], [0, 1, 1], [1, -1, 1]], dtype=np.int16)
+    values = np.array([1, 0, 1], dtype=np.int16)
+    is_smaller = np.array([False, True, True])
+    is_equal = np.array([True, True, False])
+    results = []
+    for bulk in [True, False]:
+        g = CheckGraph(3, 2)
+        g.add_var_nodes({-1: 0.25, 0: 0.5, 1: 0.25})
+        if bulk:
+            assert(g.add_equations(mat, values, is_smaller, is_equal, threads=2) == [3, 4, 5])
+        else:
+            for i, row in enumerate(mat):
+                g.add_equation("Line {}".format(i), row.tolist(), int(values[i]), bool(is_smaller[i]), bool(is_equal[i]))
+        g.ini()
+        g.propagate(4, 1)
+        results.append([g.get_result(i) for i in range(3)])
+    for r0, r1 in zip(*results):
+        for v in r0:
+            assert(abs(r0[v] - r1[v]) <= 0.000001)
+    try:
+        g.add_equations(mat[:, :2], values, is_smaller, is_equal)
+        assert(False)
+    except DimensionError:
+        pass
+    try:
+        g.add_equations(mat, values[:2], is_smaller, is_equal)
+        assert(False)
+    except DimensionError:
+        pass
+
+def test_check_bp_marginals():
+    g = CheckGraph(3, 2)
+    g.add_var_nodes({-1: 0.25, 0: 0.5, 1: 0.25})
+    g.add_equality("0", [1, 1], 2, indices=[0, 1])
+    #Does not restrict the third variable
+    g.add_interval("1", [1, 1], -1, 2, indices=[1, 2])
+    g.ini()
+    g.propagate(2, 1)
+    marginals, entropies, argmax, max_probs = g.get_marginals(2)
+    assert(marginals.shape == (3, 5))
+    for i in range(3):
+        res = g.get_result(i)
+        for v, p in res.items():
+            assert(abs(marginals[i, v + 2] - p) <= 0.000001)
+    assert(list(argmax[:2]) == [1, 1])
+    assert(abs(max_probs[0] - 1) <= 0.000001)
+    assert(entropies[0] < entropies[2])
+    marginals, entropies, argmax, max_probs = g.get_marginals(nodes=[2, 0])
+    assert(marginals.shape == (2, 5) and argmax[0] == 0 and argmax[1] == 1)
+    try:
+        g.get_marginals(nodes=[3])
+        assert(False)
+    except NodeNotFoundError:
+        pass
+
+def test_check_bp_key_evaluator():
+    marginals = np.array([[0.0, 0.1, 0.8, 0.1, 0.0],
+                          [0.0, 0.2, 0.3, 0.5, 0.0],
+                          [0.1, 0.4, 0.2, 0.2, 0.1],
+                          [0.0, 0.0, 0.0, 1.0, 0.0]])
+    entropies = np.array([0.9, 1.5, 2.0, 0.0])
+    evaluator = KeyEvaluator([0, 1, 0, 1])
+    metrics = evaluator.evaluate_marginals(marginals, entropies)
+    assert(metrics.correct == 3)
+    assert(metrics.rank_counts == [3, 1])
+    assert(abs(metrics.avg_prob - 0.625) <= 0.000001)
+    assert(abs(metrics.log2_rank_lower_bound - 1) <= 0.000001)
+    #More likely: -1 in the third coefficient with 0 or 1 in the second, as likely: 1 in the third
+    lower, upper = metrics.log2_rank_bounds
+    assert(abs(lower - np.log2(3)) <= 0.000001 and abs(upper - 2) <= 0.000001)
+    #A subnormal probability of the correct value still gives finite bounds
+    subnormal = KeyEvaluator([-2, 1, 0, 1], 64).evaluate_marginals(marginals + 1e-320, entropies)
+    lower, upper = subnormal.log2_rank_bounds
+    assert(np.isfinite(lower) and np.isfinite(upper) and lower <= upper)
+    #Most likely: 3, 0, 1, 2 and lowest entropy: 3, 0, 1, 2
+    assert(metrics.len_correct_prob == 2 and metrics.len_correct_ent == 2)
+    assert(metrics.len_correct_ent_diff is None)
+    assert(metrics.success)
+    metrics = evaluator.evaluate_marginals(marginals, entropies)
+    assert(metrics.len_correct_ent_diff == 2)
+    g = CheckGraph(2, 2)
+    g.add_var_nodes({-1: 0.25, 0: 0.5, 1: 0.25})
+    g.add_equality("0", [1, 1], 2)
+    g.ini()
+    g.propagate(2, 1)
+    metrics = KeyEvaluator([1, 1]).evaluate(g)
+    assert(metrics.correct == 2 and metrics.max_rank == 0)
+    try:
+        KeyEvaluator([1]).evaluate(g)
+        assert(False)
+    except DimensionError:
+        pass
+
+def test_check_bp_decimation():
+    g = CheckGraph(3, 2)
+    g.add_var_nodes({-1: 0.25, 0: 0.5, 1: 0.25})
+    g.add_equality("0", [1, 1], 2, indices=[0, 1])
+    g.add_interval("1", [1, 1], -1, 2, indices=[1, 2])
+    try:
+        g.decimate()
+        assert(False)
+    except CheckGraphError:
+        pass
+    g.ini()
+    report = g.decimate(rounds=4, steps=2)
+    assert(report.values == {0: 1, 1: 1})
+    assert(sorted([(n, v) for n, v, _ in report.fixed]) == [(0, 1), (1, 1)])
+    assert(report.unfixed == [] and report.contradictions == 0)
+    assert(g.get_fixed(0) and g.get_fixed(1) and not g.get_fixed(2))
+    try:
+        g.decimate(min_prob=0.5, unfix_prob=0.6)
+        assert(False)
+    except CheckGraphError:
+        pass
+
+def test_check_bp_evidence(path="/tmp/test_check_bp_evidence.cbpg"):
+    g = CheckGraph(2, 2)
+    g.add_var_nodes({-1: 1/3, 0: 1/3, 1: 1/3})
+    g.add_interval("0", [1, 1], -2, 2)
+    g.ini()
+    try:
+        #Contradicts the prior
+        g.set_evidence(0, [0, 0, 0, 0, 1])
+        assert(False)
+    except CheckGraphError:
+        pass
+    g.set_evidence(0, [0, 1, 2, 1, 0])
+    belief = g.get_belief(0)
+    assert(abs(belief[0] - 0.5) <= 0.000001 and abs(belief[1] - 0.25) <= 0.000001)
+    g.propagate(2, 1)
+    res = g.get_result(0)
+    assert(res[0] > res[1])
+    g.save(path)
+    h = CheckGraph.load(path)
+    assert(abs(h.get_belief(0)[0] - 0.5) <= 0.000001)
+    g.set_fixed(0, 1)
+    assert(abs(g.get_belief(0)[1] - 1) <= 0.000001)
+    g.unfix(0)
+    assert(not g.get_fixed(0))
+    g.clear_evidence(0)
+    g.set_prior(1, [0, 0, 0, 1, 0])
+    g.propagate(2, 1)
+    assert(abs(g.get_belief(1)[1] - 1) <= 0.000001)
+    try:
+        g.set_prior(1, [0.5, 0.5])
+        assert(False)
+    except DimensionError:
+        pass
+
+def test_check_bp_errors():
+    g = CheckGraph(3, 2)
+    g.add_var_nodes({0: 0.5, 1: 0.5})
+    try:
+        g.add_equation("0", [1, 1], 0, True, False)
+        assert(False)
+    except DimensionError:
+        pass
+    try:
+        g.get_fixed(3)
+        assert(False)
+    except NodeNotFoundError as e:
+        assert(e.args[1] == 3)
+    try:
+        g.set_fixed(0, 3)
+        assert(False)
+    except CheckGraphError as e:
+        assert(e.args[1] == 0)
+
+def test_check_bp_log_domain():
+    #Many identical inequalities underflow the products of the linear domain
+    g = CheckGraph(2, 2, True)
+    assert(g.log_domain)
+    g.add_var_nodes({-2: 0.05, -1: 0.25, 0: 0.4, 1: 0.25, 2: 0.05})
+    for i in range(3000):
+        g.add_equation(str(i), [1, 1], 1, True, True, 0.3)
+    g.ini()
+    g.propagate(2, 1)
+    res = g.get_result(0)
+    assert(abs(sum(res.values()) - 1) <= 0.000001)
+    assert(res[-2] > res[2])
+
+def test_check_bp_schedules():
+    for schedule in ["flooding", "serial", "residual"]:
+        g = CheckGraph(3, 2)
+        g.add_var_nodes({-1: 1/3, 0: 1/3, 1: 1/3})
+        g.add_equality("0", [1, 1], 2, indices=[0, 1])
+        g.add_equality("1", [1, 1], 1, indices=[1, 2])
+        g.add_interval("2", [1, 1, 1], 0, 2)
+        g.damping = 0.3
+        g.set_schedule(schedule, groups=3, fraction=0.5)
+        assert(g.schedule == schedule)
+        g.ini()
+        g.propagate(30, 1)
+        for i, expected in enumerate([1, 1, 0]):
+            assert(g.get_result(i)[expected] > 0.99)
+    try:
+        g.damping = 1.0
+        assert(False)
+    except CheckGraphError:
+        pass
+
+def test_check_bp_statistics():
+    g = CheckGraph(3, 2)
+    g.add_var_nodes({-1: 1/3, 0: 1/3, 1: 1/3})
+    g.add_equality("0", [1, 1], 2, indices=[0, 1])
+    g.add_interval("1", [1, 1, 1], 0, 2)
+    g.ini()
+    assert(g.propagate_until(20, 0.000001))
+    stats = g.statistics
+    assert(len(stats) >= 2)
+    assert(stats[0].max_change is None)
+    assert(stats[-1].max_change <= 0.000001)
+    assert(stats[-1].argmax_changes == 0)
+    assert(stats[-1].step == 2*len(stats))
+    assert(g.last_statistics.iteration == len(stats) - 1)
+
+def test_check_bp_progress():
+    g = CheckGraph(3, 2)
+    g.add_var_nodes({-1: 1/3, 0: 1/3, 1: 1/3})
+    g.add_equality("0", [1, 1], 2, indices=[0, 1])
+    g.add_interval("1", [1, 1, 1], 0, 2)
+    g.ini()
+    progress = []
+    g.propagate(3, 1, lambda done, steps: progress.append((done, steps)))
+    assert(progress == [(1, 3), (2, 3), (3, 3)])
+    def interrupt(done, steps):
+        if done == 2:
+            raise KeyboardInterrupt()
+    try:
+        g.propagate(10, 1, callback=interrupt)
+        assert(False)
+    except KeyboardInterrupt:
+        pass
+    #The graph stays usable after an interruption
+    g.propagate(20, 1)
+    assert(g.get_result(0)[1] > 0.99)
+
+def test_check_bp_checkpoint(path="/tmp/test_check_bp.cbpg"):
+    def build():
+        g = CheckGraph(4, 2)
+        g.add_var_nodes({-2: 0.05, -1: 0.25, 0: 0.4, 1: 0.25, 2: 0.05})
+        g.add_equation("0", [1, 2, 0, -1], 1, True, True, 0.1)
+        g.add_equality("1", [1, 1], 0, indices=[1, 2])
+        g.add_interval("2", [2, 1, 1], -1, 3, indices=[0, 2, 3])
+        g.set_fixed(3, 1)
+        g.damping = 0.2
+        g.ini()
+        return g
+    g = build()
+    g.propagate(4, 1)
+    g.save(path)
+    g.propagate(4, 1)
+    h = CheckGraph.load(path)
+    assert(h.get_fixed(3))
+    assert(h.damping == 0.2)
+    h.propagate(4, 1)
+    for i in range(4):
+        for v, p in g.get_result(i).items():
+            assert(abs(h.get_result(i)[v] - p) <= 0.000001)
+    try:
+        with open(path, "wb") as f:
+            f.write(b"CBPG")
+        CheckGraph.load(path)
+        assert(False)
+    except CheckGraphError:
+        pass
+
+def test_check_bp_fft_size():
+    def build(fft_size):
+        g = CheckGraph(3, 2)
+        g.fft_size = fft_size
+        g.add_var_nodes({-2: 0.05, -1: 0.25, 0: 0.4, 1: 0.25, 2: 0.05})
+        g.add_equation("0", [300, -250, 200], 400, True, True)
+        g.add_equation("1", [1, 1, 1], -1, False, True)
+        g.ini()
+        g.propagate(4, 1)
+        return g
+    exact = build(None)
+    assert(exact.aliased_mass == 0)
+    aliased = build(1024)
+    assert(aliased.aliased_mass > 0)
+    #Every possible sum of this row needs a transform larger than 2^20
+    dense = CheckGraph(2048, 2)
+    dense.add_var_nodes({-2: 0.05, -1: 0.25, 0: 0.4, 1: 0.25, 2: 0.05})
+    try:
+        dense.add_equation("0", [200] * 2048, 0, True, True)
+        assert(False)
+    except DimensionError:
+        pass
+    try:
+        g = CheckGraph(3, 2)
+        g.fft_size = 1000
+        assert(False)
+    except DimensionError:
+        pass
+
+def test_random_equations(key_len=1024, eq_count=8000, max_err=20, log_domain=False):
+    g = CheckGraph(key_len, 2, log_domain)
     g.set_check_validity(True)
     key = [random.randint(-2, 2) for _ in range(key_len)]
     print(key)
@@ -142,6 +526,24 @@ def test_fft_py():
 
 
 def test_check_bp():
+    test_check_bp_sparse()
+    test_check_bp_single_connection()
+    test_check_bp_noisy()
+    test_check_bp_equalities()
+    test_check_bp_priors()
+    test_check_bp_errors()
+    test_check_bp_add_equations()
+    test_check_bp_marginals()
+    test_check_bp_key_evaluator()
+    test_check_bp_decimation()
+    test_check_bp_evidence()
+    test_check_bp_log_domain()
+    test_check_bp_schedules()
+    test_check_bp_statistics()
+    test_check_bp_progress()
+    test_check_bp_checkpoint()
+    test_check_bp_fft_size()
+    test_random_equations(log_domain=True)
     test_random_equations()
     return
     test_fft_py()
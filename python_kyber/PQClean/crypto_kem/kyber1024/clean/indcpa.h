@@ -5,12 +5,22 @@
 #include <stdint.h>
 
 void PQCLEAN_KYBER1024_CLEAN_gen_matrix(polyvec *a, const uint8_t seed[KYBER_SYMBYTES], int transposed);
+void PQCLEAN_KYBER1024_CLEAN_pack_pk(uint8_t r[KYBER_INDCPA_PUBLICKEYBYTES],
+                                     polyvec *pk,
+                                     const uint8_t seed[KYBER_SYMBYTES]);
+void PQCLEAN_KYBER1024_CLEAN_unpack_pk(polyvec *pk,
+                                       uint8_t seed[KYBER_SYMBYTES],
+                                       const uint8_t packedpk[KYBER_INDCPA_PUBLICKEYBYTES]);
 void PQCLEAN_KYBER1024_CLEAN_indcpa_keypair(uint8_t pk[KYBER_INDCPA_PUBLICKEYBYTES],
         uint8_t sk[KYBER_INDCPA_SECRETKEYBYTES]);
 
 void PQCLEAN_KYBER1024_CLEAN_indcpa_keypair_manip(uint8_t pk[KYBER_INDCPA_PUBLICKEYBYTES],
         uint8_t sk[KYBER_INDCPA_SECRETKEYBYTES], polyvec*);
 
+void PQCLEAN_KYBER1024_CLEAN_indcpa_keypair_manip_derand(uint8_t pk[KYBER_INDCPA_PUBLICKEYBYTES],
+        uint8_t sk[KYBER_INDCPA_SECRETKEYBYTES], polyvec*,
+        const uint8_t coins[KYBER_SYMBYTES]);
+
 void PQCLEAN_KYBER1024_CLEAN_indcpa_enc(uint8_t c[KYBER_INDCPA_BYTES],
                                         const uint8_t m[KYBER_INDCPA_MSGBYTES],
                                         const uint8_t pk[KYBER_INDCPA_PUBLICKEYBYTES],
@@ -34,19 +34,40 @@ int PQCLEAN_KYBER512_CLEAN_crypto_kem_keypair(unsigned char pk[KYBER_PUBLICKEYBY
     return 0;
 }
 
-int PQCLEAN_KYBER512_CLEAN_crypto_kem_keypair_manip(unsigned char pk[KYBER_PUBLICKEYBYTES],
-        unsigned char sk[KYBER_SECRETKEYBYTES], polyvec* ecp) {
+/*************************************************
+* Name:        PQCLEAN_KYBER512_CLEAN_crypto_kem_keypair_manip_derand
+*
+* Description: Deterministic version of crypto_kem_keypair_manip,
+*              all randomness is taken from coins
+*
+* Arguments:   - polyvec *ecp: pointer to output error vector e
+*              - const uint8_t *coins: pointer to input randomness
+*                (an already allocated array of 2*KYBER_SYMBYTES bytes,
+*                the first half seeds the key pair, the second half is z)
+*
+* Returns 0 (success)
+**************************************************/
+int PQCLEAN_KYBER512_CLEAN_crypto_kem_keypair_manip_derand(unsigned char pk[KYBER_PUBLICKEYBYTES],
+        unsigned char sk[KYBER_SECRETKEYBYTES], polyvec* ecp,
+        const uint8_t coins[2 * KYBER_SYMBYTES]) {
     size_t i;
-    PQCLEAN_KYBER512_CLEAN_indcpa_keypair_manip(pk, sk, ecp);
+    PQCLEAN_KYBER512_CLEAN_indcpa_keypair_manip_derand(pk, sk, ecp, coins);
     for (i = 0; i < KYBER_INDCPA_PUBLICKEYBYTES; i++) {
         sk[i + KYBER_INDCPA_SECRETKEYBYTES] = pk[i];
     }
     hash_h(sk + KYBER_SECRETKEYBYTES - 2 * KYBER_SYMBYTES, pk, KYBER_PUBLICKEYBYTES);
     /* Value z for pseudo-random output on reject */
-    randombytes(sk + KYBER_SECRETKEYBYTES - KYBER_SYMBYTES, KYBER_SYMBYTES);
+    memcpy(sk + KYBER_SECRETKEYBYTES - KYBER_SYMBYTES, coins + KYBER_SYMBYTES, KYBER_SYMBYTES);
     return 0;
 }
 
+int PQCLEAN_KYBER512_CLEAN_crypto_kem_keypair_manip(unsigned char pk[KYBER_PUBLICKEYBYTES],
+        unsigned char sk[KYBER_SECRETKEYBYTES], polyvec* ecp) {
+    uint8_t coins[2 * KYBER_SYMBYTES];
+    randombytes(coins, 2 * KYBER_SYMBYTES);
+    return PQCLEAN_KYBER512_CLEAN_crypto_kem_keypair_manip_derand(pk, sk, ecp, coins);
+}
+
 /*************************************************
 * Name:        PQCLEAN_KYBER512_CLEAN_crypto_kem_enc
 *
@@ -87,18 +108,30 @@ int PQCLEAN_KYBER512_CLEAN_crypto_kem_enc(unsigned char ct[KYBER_CIPHERTEXTBYTES
     return 0;
 }
 
-int PQCLEAN_KYBER512_CLEAN_crypto_kem_enc_manip(unsigned char ct[KYBER_CIPHERTEXTBYTES],
+/*************************************************
+* Name:        PQCLEAN_KYBER512_CLEAN_crypto_kem_enc_manip_derand
+*
+* Description: Deterministic version of crypto_kem_enc_manip,
+*              the message is derived from coins
+*
+* Arguments:   - const uint8_t *coins: pointer to input randomness
+*                (an already allocated array of KYBER_SYMBYTES bytes)
+*
+* Returns 0 (success)
+**************************************************/
+int PQCLEAN_KYBER512_CLEAN_crypto_kem_enc_manip_derand(unsigned char ct[KYBER_CIPHERTEXTBYTES],
         unsigned char ss[KYBER_SSBYTES],
         const unsigned char pk[KYBER_PUBLICKEYBYTES],
                                         uint8_t* nu,
                                         polyvec* r,
                                         polyvec* e1,
-                                        poly* e2) {
+                                        poly* e2,
+                                        const uint8_t coins[KYBER_SYMBYTES]) {
     uint8_t buf[2 * KYBER_SYMBYTES];
     /* Will contain key, coins */
     uint8_t kr[2 * KYBER_SYMBYTES];
 
-    randombytes(buf, KYBER_SYMBYTES);
+    memcpy(buf, coins, KYBER_SYMBYTES);
     /* Don't release system RNG output */
     hash_h(buf, buf, KYBER_SYMBYTES);
 
@@ -118,6 +151,18 @@ int PQCLEAN_KYBER512_CLEAN_crypto_kem_enc_manip(unsigned char ct[KYBER_CIPHERTEX
     return 0;
 }
 
+int PQCLEAN_KYBER512_CLEAN_crypto_kem_enc_manip(unsigned char ct[KYBER_CIPHERTEXTBYTES],
+        unsigned char ss[KYBER_SSBYTES],
+        const unsigned char pk[KYBER_PUBLICKEYBYTES],
+                                        uint8_t* nu,
+                                        polyvec* r,
+                                        polyvec* e1,
+                                        poly* e2) {
+    uint8_t coins[KYBER_SYMBYTES];
+    randombytes(coins, KYBER_SYMBYTES);
+    return PQCLEAN_KYBER512_CLEAN_crypto_kem_enc_manip_derand(ct, ss, pk, nu, r, e1, e2, coins);
+}
+
 /*************************************************
 * Name:        PQCLEAN_KYBER512_CLEAN_crypto_kem_dec
 *
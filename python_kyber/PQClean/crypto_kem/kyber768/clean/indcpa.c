@@ -11,7 +11,7 @@
 #include <stdio.h>
 
 /*************************************************
-* Name:        pack_pk
+* Name:        PQCLEAN_KYBER768_CLEAN_pack_pk
 *
 * Description: Serialize the public key as concatenation of the
 *              serialized vector of polynomials pk
@@ -21,7 +21,7 @@
 *              polyvec *pk: pointer to the input public-key polyvec
 *              const uint8_t *seed: pointer to the input public seed
 **************************************************/
-void pack_pk(uint8_t r[KYBER_INDCPA_PUBLICKEYBYTES],
+void PQCLEAN_KYBER768_CLEAN_pack_pk(uint8_t r[KYBER_INDCPA_PUBLICKEYBYTES],
                     polyvec *pk,
                     const uint8_t seed[KYBER_SYMBYTES]) {
     size_t i;
@@ -32,16 +32,16 @@ void pack_pk(uint8_t r[KYBER_INDCPA_PUBLICKEYBYTES],
 }
 
 /*************************************************
-* Name:        unpack_pk
+* Name:        PQCLEAN_KYBER768_CLEAN_unpack_pk
 *
 * Description: De-serialize public key from a byte array;
-*              approximate inverse of pack_pk
+*              approximate inverse of PQCLEAN_KYBER768_CLEAN_pack_pk
 *
 * Arguments:   - polyvec *pk: pointer to output public-key polynomial vector
 *              - uint8_t *seed: pointer to output seed to generate matrix A
 *              - const uint8_t *packedpk: pointer to input serialized public key
 **************************************************/
-void unpack_pk(polyvec *pk,
+void PQCLEAN_KYBER768_CLEAN_unpack_pk(polyvec *pk,
                       uint8_t seed[KYBER_SYMBYTES],
                       const uint8_t packedpk[KYBER_INDCPA_PUBLICKEYBYTES]) {
     size_t i;
@@ -59,7 +59,7 @@ void unpack_pk(polyvec *pk,
 * Arguments:   - uint8_t *r: pointer to output serialized secret key
 *              - polyvec *sk: pointer to input vector of polynomials (secret key)
 **************************************************/
-void pack_sk(uint8_t r[KYBER_INDCPA_SECRETKEYBYTES], polyvec *sk) {
+static void pack_sk(uint8_t r[KYBER_INDCPA_SECRETKEYBYTES], polyvec *sk) {
     PQCLEAN_KYBER768_CLEAN_polyvec_tobytes(r, sk);
 }
 
@@ -71,7 +71,7 @@ void pack_sk(uint8_t r[KYBER_INDCPA_SECRETKEYBYTES], polyvec *sk) {
 * Arguments:   - polyvec *sk: pointer to output vector of polynomials (secret key)
 *              - const uint8_t *packedsk: pointer to input serialized secret key
 **************************************************/
-void unpack_sk(polyvec *sk, const uint8_t packedsk[KYBER_INDCPA_SECRETKEYBYTES]) {
+static void unpack_sk(polyvec *sk, const uint8_t packedsk[KYBER_INDCPA_SECRETKEYBYTES]) {
     PQCLEAN_KYBER768_CLEAN_polyvec_frombytes(sk, packedsk);
 }
 
@@ -86,7 +86,7 @@ void unpack_sk(polyvec *sk, const uint8_t packedsk[KYBER_INDCPA_SECRETKEYBYTES])
 *              poly *pk: pointer to the input vector of polynomials b
 *              poly *v: pointer to the input polynomial v
 **************************************************/
-void pack_ciphertext(uint8_t r[KYBER_INDCPA_BYTES], polyvec *b, poly *v) {
+static void pack_ciphertext(uint8_t r[KYBER_INDCPA_BYTES], polyvec *b, poly *v) {
     PQCLEAN_KYBER768_CLEAN_polyvec_compress(r, b);
     PQCLEAN_KYBER768_CLEAN_poly_compress(r + KYBER_POLYVECCOMPRESSEDBYTES, v);
 }
@@ -101,7 +101,7 @@ void pack_ciphertext(uint8_t r[KYBER_INDCPA_BYTES], polyvec *b, poly *v) {
 *              - poly *v: pointer to the output polynomial v
 *              - const uint8_t *c: pointer to the input serialized ciphertext
 **************************************************/
-void unpack_ciphertext(polyvec *b, poly *v, const uint8_t c[KYBER_INDCPA_BYTES]) {
+static void unpack_ciphertext(polyvec *b, poly *v, const uint8_t c[KYBER_INDCPA_BYTES]) {
     PQCLEAN_KYBER768_CLEAN_polyvec_decompress(b, c);
     PQCLEAN_KYBER768_CLEAN_poly_decompress(v, c + KYBER_POLYVECCOMPRESSEDBYTES);
 }
@@ -119,7 +119,7 @@ void unpack_ciphertext(polyvec *b, poly *v, const uint8_t c[KYBER_INDCPA_BYTES])
 *
 * Returns number of sampled 16-bit integers (at most len)
 **************************************************/
-unsigned int rej_uniform(int16_t *r,
+static unsigned int rej_uniform(int16_t *r,
                                 unsigned int len,
                                 const uint8_t *buf,
                                 unsigned int buflen) {
@@ -237,11 +237,12 @@ void PQCLEAN_KYBER768_CLEAN_indcpa_keypair(uint8_t pk[KYBER_INDCPA_PUBLICKEYBYTE
     PQCLEAN_KYBER768_CLEAN_polyvec_reduce(&pkpv);
 
     pack_sk(sk, &skpv);
-    pack_pk(pk, &pkpv, publicseed);
+    PQCLEAN_KYBER768_CLEAN_pack_pk(pk, &pkpv, publicseed);
 }
 
-void PQCLEAN_KYBER768_CLEAN_indcpa_keypair_manip(uint8_t pk[KYBER_INDCPA_PUBLICKEYBYTES],
-        uint8_t sk[KYBER_INDCPA_SECRETKEYBYTES], polyvec* ecp) {
+void PQCLEAN_KYBER768_CLEAN_indcpa_keypair_manip_derand(uint8_t pk[KYBER_INDCPA_PUBLICKEYBYTES],
+        uint8_t sk[KYBER_INDCPA_SECRETKEYBYTES], polyvec* ecp,
+        const uint8_t coins[KYBER_SYMBYTES]) {
     unsigned int i;
     uint8_t buf[2 * KYBER_SYMBYTES];
     const uint8_t *publicseed = buf;
@@ -249,8 +250,7 @@ void PQCLEAN_KYBER768_CLEAN_indcpa_keypair_manip(uint8_t pk[KYBER_INDCPA_PUBLICK
     uint8_t nonce = 0;
     polyvec a[KYBER_K], e, pkpv, skpv;
 
-    randombytes(buf, KYBER_SYMBYTES);
-    hash_g(buf, buf, KYBER_SYMBYTES);
+    hash_g(buf, coins, KYBER_SYMBYTES);
 
     gen_a(a, publicseed);
 
@@ -275,7 +275,14 @@ void PQCLEAN_KYBER768_CLEAN_indcpa_keypair_manip(uint8_t pk[KYBER_INDCPA_PUBLICK
     PQCLEAN_KYBER768_CLEAN_polyvec_reduce(&pkpv);
 
     pack_sk(sk, &skpv);
-    pack_pk(pk, &pkpv, publicseed);
+    PQCLEAN_KYBER768_CLEAN_pack_pk(pk, &pkpv, publicseed);
+}
+
+void PQCLEAN_KYBER768_CLEAN_indcpa_keypair_manip(uint8_t pk[KYBER_INDCPA_PUBLICKEYBYTES],
+        uint8_t sk[KYBER_INDCPA_SECRETKEYBYTES], polyvec* ecp) {
+    uint8_t coins[KYBER_SYMBYTES];
+    randombytes(coins, KYBER_SYMBYTES);
+    PQCLEAN_KYBER768_CLEAN_indcpa_keypair_manip_derand(pk, sk, ecp, coins);
 }
 
 /*************************************************
@@ -304,7 +311,7 @@ void PQCLEAN_KYBER768_CLEAN_indcpa_enc(uint8_t c[KYBER_INDCPA_BYTES],
     polyvec sp, pkpv, ep, at[KYBER_K], b;
     poly v, k, epp;
 
-    unpack_pk(&pkpv, seed, pk);
+    PQCLEAN_KYBER768_CLEAN_unpack_pk(&pkpv, seed, pk);
     PQCLEAN_KYBER768_CLEAN_poly_frommsg(&k, m);
     gen_at(at, seed);
 
@@ -350,7 +357,7 @@ void PQCLEAN_KYBER768_CLEAN_indcpa_enc_manip(uint8_t c[KYBER_INDCPA_BYTES],
     polyvec sp, pkpv, ep, at[KYBER_K], b;
     poly v, k, epp;
 
-    unpack_pk(&pkpv, seed, pk);
+    PQCLEAN_KYBER768_CLEAN_unpack_pk(&pkpv, seed, pk);
     PQCLEAN_KYBER768_CLEAN_poly_frommsg(&k, m);
     gen_at(at, seed);
 
@@ -1,29 +0,0 @@
-pub mod ciphertext;
-pub mod constants;
-pub mod kyber_sample;
-pub mod poly;
-pub mod polyvec;
-pub mod public_key;
-pub mod secret_key;
-#[cfg(test)]
-mod test;
-#[cfg(feature = "kyber1024")]
-pub mod wrapper1024;
-#[cfg(feature = "kyber768")]
-pub mod wrapper768;
-#[cfg(feature = "kyber512")]
-pub mod wrapper512;
-
-#[cfg(feature = "kyber1024")]
-pub use self::wrapper1024 as wrapper;
-#[cfg(feature = "kyber768")]
-pub use self::wrapper768 as wrapper;
-#[cfg(feature = "kyber512")]
-pub use self::wrapper512 as wrapper;
-
-pub use self::ciphertext::Ciphertext;
-pub use self::kyber_sample::KyberSample;
-pub use self::poly::Poly;
-pub use self::polyvec::Polyvec;
-pub use self::public_key::PublicKey;
-pub use self::secret_key::SecretKey;
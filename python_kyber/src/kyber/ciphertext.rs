@@ -1,6 +1,6 @@
-use crate::constants::*;
-use crate::kyber::wrapper::*;
-use crate::{Poly, Polyvec};
+use super::constants::*;
+use super::wrapper::*;
+use super::{Poly, Polyvec};
 use pyo3::prelude::*;
 
 #[pyclass]
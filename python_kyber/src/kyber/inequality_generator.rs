@@ -0,0 +1,378 @@
+use super::constants::*;
+use super::wrapper::*;
+use super::{Ciphertext, KyberSample, Poly, Polyvec};
+use numpy::{PyArray1, PyArray2};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use rand::{Rng, RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use std::thread;
+
+//Error added to the manipulated coefficient of v
+const ERR: i16 = (Q / 4) as i16;
+//Inequalities generated from one derived seed, the result of a seed does
+//not depend on the number of threads
+const CHUNK: usize = 64;
+
+struct Inequality {
+    row: Vec<i16>,
+    b: i16,
+    is_le: bool,
+    is_eq: bool,
+    coeff_index: usize,
+}
+
+#[derive(Default)]
+struct Inequalities {
+    mat_ge: Vec<i16>,
+    mat_le: Vec<i16>,
+    vec_ge: Vec<i16>,
+    vec_le: Vec<i16>,
+    eq_ge: Vec<bool>,
+    eq_le: Vec<bool>,
+    idx_ge: Vec<usize>,
+    idx_le: Vec<usize>,
+}
+
+impl Inequalities {
+    fn push(&mut self, ineq: Inequality) {
+        if ineq.is_le {
+            self.mat_le.extend(ineq.row);
+            self.vec_le.push(ineq.b);
+            self.eq_le.push(ineq.is_eq);
+            self.idx_le.push(ineq.coeff_index);
+        } else {
+            self.mat_ge.extend(ineq.row);
+            self.vec_ge.push(ineq.b);
+            self.eq_ge.push(ineq.is_eq);
+            self.idx_ge.push(ineq.coeff_index);
+        }
+    }
+    fn append(&mut self, mut other: Inequalities) {
+        self.mat_ge.append(&mut other.mat_ge);
+        self.mat_le.append(&mut other.mat_le);
+        self.vec_ge.append(&mut other.vec_ge);
+        self.vec_le.append(&mut other.vec_le);
+        self.eq_ge.append(&mut other.eq_ge);
+        self.eq_le.append(&mut other.eq_le);
+        self.idx_ge.append(&mut other.idx_ge);
+        self.idx_le.append(&mut other.idx_le);
+    }
+    fn len(&self) -> usize {
+        self.vec_ge.len() + self.vec_le.len()
+    }
+}
+
+//Simulates faults in the re-encryption of the decapsulation for a fixed key
+//and returns the resulting inequalities in the key (e, s)
+#[pyclass]
+pub struct InequalityGenerator {
+    sample: KyberSample,
+    #[pyo3(get, set)]
+    add_in_vec: bool,
+    #[pyo3(get, set)]
+    max_delta_v: i16,
+    #[pyo3(get)]
+    threads: usize,
+    //Coefficients of v to manipulate, one is chosen uniformly per ciphertext
+    #[pyo3(get)]
+    coeff_indices: Vec<usize>,
+}
+
+#[pymethods]
+impl InequalityGenerator {
+    #[new]
+    pub fn new(sample: &KyberSample, threads: usize, add_in_vec: bool) -> PyResult<Self> {
+        let mut generator = InequalityGenerator {
+            sample: *sample,
+            add_in_vec: add_in_vec,
+            max_delta_v: 10,
+            threads: 1,
+            coeff_indices: vec![0],
+        };
+        generator.set_threads(threads)?;
+        Ok(generator)
+    }
+
+    #[setter]
+    pub fn set_threads(&mut self, threads: usize) -> PyResult<()> {
+        if threads == 0 {
+            return Err(PyValueError::new_err(
+                "Number of threads has to be positive.",
+            ));
+        }
+        self.threads = threads;
+        Ok(())
+    }
+
+    #[setter]
+    pub fn set_coeff_indices(&mut self, coeff_indices: Vec<usize>) -> PyResult<()> {
+        if coeff_indices.is_empty() {
+            return Err(PyValueError::new_err("No coefficient index given."));
+        }
+        if coeff_indices.iter().any(|i| *i >= N) {
+            return Err(PyValueError::new_err("Coefficient index out of range."));
+        }
+        self.coeff_indices = coeff_indices;
+        Ok(())
+    }
+
+    #[getter]
+    pub fn row_length(&self) -> usize {
+        if self.add_in_vec {
+            2 * K * N
+        } else {
+            2 * K * N + 1
+        }
+    }
+
+    //Returns mat_ge, mat_le, vec_ge, vec_le, eq_ge, eq_le, idx_ge, idx_le
+    //where idx_* are the manipulated coefficients
+    //All randomness is derived from seed if given, the result does not
+    //depend on the number of threads
+    pub fn generate<'py>(
+        &self,
+        py: Python<'py>,
+        number: usize,
+        seed: Option<u64>,
+    ) -> PyResult<(
+        &'py PyArray2<i16>,
+        &'py PyArray2<i16>,
+        &'py PyArray1<i16>,
+        &'py PyArray1<i16>,
+        &'py PyArray1<bool>,
+        &'py PyArray1<bool>,
+        &'py PyArray1<usize>,
+        &'py PyArray1<usize>,
+    )> {
+        let mut rng = match seed {
+            Some(seed) => ChaCha20Rng::seed_from_u64(seed),
+            None => ChaCha20Rng::from_entropy(),
+        };
+        //(seed, count) per chunk, thread t generates the chunks t, t+threads, ..
+        let chunks: Vec<(u64, usize)> = (0..number)
+            .step_by(CHUNK)
+            .map(|start| (rng.next_u64(), std::cmp::min(CHUNK, number - start)))
+            .collect();
+        let sample = self.sample;
+        let add_in_vec = self.add_in_vec;
+        let max_delta_v = self.max_delta_v;
+        let threads = self.threads;
+        let coeff_indices = self.coeff_indices.clone();
+        let res = py.allow_threads(move || {
+            let handles: Vec<_> = (0..threads)
+                .map(|t| {
+                    let chunks: Vec<(u64, usize)> =
+                        chunks.iter().skip(t).step_by(threads).cloned().collect();
+                    let coeff_indices = coeff_indices.clone();
+                    thread::spawn(move || {
+                        chunks
+                            .into_iter()
+                            .map(|(chunk_seed, count)| {
+                                generate_inequalities(
+                                    sample,
+                                    count,
+                                    chunk_seed,
+                                    add_in_vec,
+                                    max_delta_v,
+                                    &coeff_indices,
+                                )
+                            })
+                            .collect::<Vec<Inequalities>>()
+                    })
+                })
+                .collect();
+            let mut per_thread: Vec<std::vec::IntoIter<Inequalities>> = handles
+                .into_iter()
+                .map(|h| h.join().unwrap().into_iter())
+                .collect();
+            //Reassemble in the order of the chunks
+            let mut res = Inequalities::default();
+            for c in 0..chunks.len() {
+                res.append(per_thread[c % threads].next().unwrap());
+            }
+            res
+        });
+        let cols = self.row_length();
+        let ge_rows = res.vec_ge.len();
+        let le_rows = res.vec_le.len();
+        Ok((
+            PyArray1::from_vec(py, res.mat_ge).reshape([ge_rows, cols])?,
+            PyArray1::from_vec(py, res.mat_le).reshape([le_rows, cols])?,
+            PyArray1::from_vec(py, res.vec_ge),
+            PyArray1::from_vec(py, res.vec_le),
+            PyArray1::from_vec(py, res.eq_ge),
+            PyArray1::from_vec(py, res.eq_le),
+            PyArray1::from_vec(py, res.idx_ge),
+            PyArray1::from_vec(py, res.idx_le),
+        ))
+    }
+
+    //Returns None if the sample does not lead to an inequality,
+    //otherwise (is_le, row, b, is_eq)
+    #[staticmethod]
+    pub fn from_sample(
+        sample: &KyberSample,
+        add_in_vec: bool,
+        max_delta_v: i16,
+        coeff_index: usize,
+    ) -> PyResult<Option<(bool, Vec<i16>, i16, bool)>> {
+        if coeff_index >= N {
+            return Err(PyValueError::new_err("Coefficient index out of range."));
+        }
+        Ok(
+            inequality_from_sample(sample, add_in_vec, max_delta_v, coeff_index)
+                .map(|ineq| (ineq.is_le, ineq.row, ineq.b, ineq.is_eq)),
+        )
+    }
+}
+
+fn generate_inequalities(
+    sample: KyberSample,
+    number: usize,
+    seed: u64,
+    add_in_vec: bool,
+    max_delta_v: i16,
+    coeff_indices: &[usize],
+) -> Inequalities {
+    let mut rng = ChaCha20Rng::seed_from_u64(seed);
+    let mut pk = sample.pk;
+    let mut sk = sample.sk;
+    let pk = pk.to_bytes();
+    let sk = sk.to_bytes();
+    let mut res = Inequalities::default();
+    while res.len() < number {
+        let coeff_index = coeff_indices[rng.gen_range(0, coeff_indices.len())];
+        let sample = KyberSample::encaps_seeded(&mut rng, false, pk, sk, sample.e);
+        if let Some(ineq) = inequality_from_sample(&sample, add_in_vec, max_delta_v, coeff_index) {
+            res.push(ineq);
+        }
+    }
+    res
+}
+
+fn inequality_from_sample(
+    sample: &KyberSample,
+    add_in_vec: bool,
+    max_delta_v: i16,
+    coeff_index: usize,
+) -> Option<Inequality> {
+    let delta_v = calc_delta_v(sample);
+    if delta_v.coeffs[coeff_index].abs() >= max_delta_v {
+        return None;
+    }
+    let mut ct = sample.ct;
+    let mut ct_manip = manipulate_coefficient(&sample.ct, coeff_index, ERR);
+    if count_flipped_bits(&mut ct, &mut ct_manip) != 1 {
+        return None;
+    }
+    let is_valid = sample.is_valid_ct(&ct_manip);
+    let delta_u = calc_delta_u(sample);
+    let (row, b) = calc_row(sample, coeff_index, &delta_u, &delta_v, add_in_vec);
+    let bit_is_0 = (sample.nu[coeff_index / 8] >> (coeff_index % 8)) & 1 == 0;
+    Some(Inequality {
+        row: row,
+        b: b,
+        is_le: is_valid,
+        is_eq: if is_valid { bit_is_0 } else { !bit_is_0 },
+        coeff_index: coeff_index,
+    })
+}
+
+fn manipulate_coefficient(ct: &Ciphertext, index: usize, add_error: i16) -> Ciphertext {
+    let mut res = *ct;
+    res.v.coeffs[index] += add_error;
+    res
+}
+
+fn count_flipped_bits(ct: &mut Ciphertext, ct_manip: &mut Ciphertext) -> u32 {
+    ct.to_bytes()
+        .iter()
+        .zip(ct_manip.to_bytes().iter())
+        .map(|(b, bm)| (b ^ bm).count_ones())
+        .sum()
+}
+
+fn compress_decompress_polyvec(p: &Polyvec) -> Polyvec {
+    Polyvec::from_bytes_compressed(&p.clone().to_bytes_compressed())
+}
+
+fn compress_decompress_poly(p: &Poly) -> Poly {
+    Poly::from_bytes_compressed(&p.clone().to_bytes_compressed())
+}
+
+fn calc_delta_u(sample: &KyberSample) -> Polyvec {
+    let r = sample.r.ntt();
+    let mut at = [Polyvec::new(); K];
+    for i in 0..K {
+        for j in 0..K {
+            at[j].vec[i] = sample.pk.a[i].vec[j];
+        }
+    }
+    let mut u = r.apply_matrix_left_ntt(at.to_vec()).intt();
+    let au = u;
+    polyvec_add(&mut u, &au, &sample.e1);
+    let u = u.reduce();
+    let u_compressed = compress_decompress_polyvec(&u);
+    debug_assert_eq!(u_compressed.to_lists(), sample.ct.b.to_lists());
+    let mut delta_u = Polyvec::new();
+    for i in 0..K {
+        poly_sub(&mut delta_u.vec[i], &u_compressed.vec[i], &u.vec[i]);
+    }
+    delta_u
+}
+
+fn calc_delta_v(sample: &KyberSample) -> Poly {
+    let tr = Polyvec::scalar(&sample.pk.pk, &sample.r.ntt()).intt();
+    let mut v = Poly::new();
+    poly_add(&mut v, &tr, &sample.e2);
+    let vm = v;
+    poly_add(&mut v, &vm, &sample.get_msg());
+    let v = v.reduce();
+    let v_compressed = compress_decompress_poly(&v);
+    let mut delta_v = Poly::new();
+    poly_sub(&mut delta_v, &v_compressed, &v);
+    delta_v
+}
+
+fn reduce_sym(a: i32) -> i16 {
+    let q = Q as i32;
+    let a = a.rem_euclid(q);
+    if a > q / 2 {
+        (a - q) as i16
+    } else {
+        a as i16
+    }
+}
+
+fn calc_row(
+    sample: &KyberSample,
+    coeff_index: usize,
+    delta_u: &Polyvec,
+    delta_v: &Poly,
+    add_in_vec: bool,
+) -> (Vec<i16>, i16) {
+    //Coefficient m of a product in Z_q[X]/(X^N+1) is sum_i a_i*b_{m-i} with
+    //the sign flipped where m-i wraps around
+    let sign = |i: usize| if i <= coeff_index { 1 } else { -1 };
+    let mut e_row = Vec::with_capacity(K * N);
+    let mut s_row = Vec::with_capacity(K * N);
+    for j in 0..K {
+        for i in 0..N {
+            let idx = (N + coeff_index - i) % N;
+            e_row.push(sign(i) * sample.r.vec[j].coeffs[idx] as i32);
+            s_row.push(
+                -sign(i)
+                    * (sample.e1.vec[j].coeffs[idx] as i32 + delta_u.vec[j].coeffs[idx] as i32),
+            );
+        }
+    }
+    let mut row: Vec<i16> = e_row.into_iter().chain(s_row).map(reduce_sym).collect();
+    let v = sample.e2.coeffs[coeff_index] as i32 + delta_v.coeffs[coeff_index] as i32;
+    if add_in_vec {
+        (row, -reduce_sym(v))
+    } else {
+        row.push(reduce_sym(v));
+        (row, 0)
+    }
+}
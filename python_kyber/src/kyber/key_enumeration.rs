@@ -0,0 +1,257 @@
+use super::constants::*;
+use super::key_recovery::{key_marginals, LEN};
+use super::wrapper::*;
+use super::{Polyvec, PublicKey};
+use numpy::PyReadonlyArray2;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::thread;
+
+//Candidates checked per thread between two checks for Ctrl-C
+const BATCH: usize = 1024;
+
+//A less likely value of one coefficient, cost is ln(p_max/p)
+struct Deviation {
+    var: usize,
+    value: i16,
+    cost: f64,
+}
+
+//Set of deviations (indices into the deviations sorted by cost) from the
+//most likely key, ordered such that the heap pops the cheapest first
+struct Subset {
+    cost: f64,
+    devs: Vec<usize>,
+}
+
+impl PartialEq for Subset {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Subset {}
+
+impl PartialOrd for Subset {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Subset {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .cost
+            .partial_cmp(&self.cost)
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
+//Yields keys in decreasing joint probability (assuming independent
+//coefficients): every subset of deviations is reached exactly once from the
+//empty one by appending the next deviation or replacing the last one by it
+//Appending keeps all deviations, so subsets whose last deviation changes a
+//coefficient changed before are not appended to
+struct Candidates {
+    best: Vec<i16>,
+    devs: Vec<Deviation>,
+    heap: BinaryHeap<Subset>,
+    started: bool,
+}
+
+impl Candidates {
+    fn new(marginals: &[Vec<f64>]) -> Self {
+        let half = (marginals[0].len() / 2) as i16;
+        let mut best = Vec::with_capacity(marginals.len());
+        let mut devs = Vec::new();
+        for (var, row) in marginals.iter().enumerate() {
+            let (idx, max) =
+                row.iter().enumerate().fold(
+                    (0, 0.0),
+                    |m, (i, p)| {
+                        if *p > m.1 {
+                            (i, *p)
+                        } else {
+                            m
+                        }
+                    },
+                );
+            best.push(idx as i16 - half);
+            for (i, p) in row.iter().enumerate() {
+                if i != idx && *p > 0.0 {
+                    devs.push(Deviation {
+                        var,
+                        value: i as i16 - half,
+                        cost: (max / p).ln(),
+                    });
+                }
+            }
+        }
+        devs.sort_by(|d0, d1| d0.cost.partial_cmp(&d1.cost).unwrap_or(Ordering::Equal));
+        Candidates {
+            best,
+            devs,
+            heap: BinaryHeap::new(),
+            started: false,
+        }
+    }
+
+    fn push(&mut self, devs: Vec<usize>) {
+        let cost = devs.iter().map(|d| self.devs[*d].cost).sum();
+        self.heap.push(Subset { cost, devs });
+    }
+}
+
+impl Iterator for Candidates {
+    type Item = Vec<i16>;
+
+    fn next(&mut self) -> Option<Vec<i16>> {
+        if !self.started {
+            self.started = true;
+            if !self.devs.is_empty() {
+                self.push(vec![0]);
+            }
+            return Some(self.best.clone());
+        }
+        while let Some(subset) = self.heap.pop() {
+            let (last, earlier) = subset.devs.split_last().unwrap();
+            let last = *last;
+            //Two values of the same coefficient are not a key, the earlier
+            //deviations never conflict as their subset was appended to
+            let valid = earlier
+                .iter()
+                .all(|other| self.devs[*other].var != self.devs[last].var);
+            if last + 1 < self.devs.len() {
+                if valid {
+                    let mut appended = subset.devs.clone();
+                    appended.push(last + 1);
+                    self.push(appended);
+                }
+                let mut replaced = subset.devs.clone();
+                *replaced.last_mut().unwrap() = last + 1;
+                self.push(replaced);
+            }
+            if valid {
+                let mut key = self.best.clone();
+                for d in subset.devs.iter() {
+                    key[self.devs[*d].var] = self.devs[*d].value;
+                }
+                return Some(key);
+            }
+        }
+        None
+    }
+}
+
+//Whether t = A*s + e for the key (e, s), computed as in the key generation
+fn matches(pk: &PublicKey, key: &[i16]) -> bool {
+    let mut e = Polyvec::new();
+    let mut s = Polyvec::new();
+    for (poly, coeffs) in e.vec.iter_mut().zip(key[..LEN].chunks(N)) {
+        poly.coeffs.copy_from_slice(coeffs);
+    }
+    for (poly, coeffs) in s.vec.iter_mut().zip(key[LEN..].chunks(N)) {
+        poly.coeffs.copy_from_slice(coeffs);
+    }
+    let mut t = s.ntt().apply_matrix_left_ntt(pk.a.to_vec());
+    for poly in t.vec.iter_mut() {
+        *poly = poly.to_mont();
+    }
+    let mut res = Polyvec::new();
+    polyvec_add(&mut res, &t, &e.ntt());
+    res.reduce().vec.iter().zip(pk.pk.vec.iter()).all(|(r, p)| {
+        r.coeffs
+            .iter()
+            .zip(p.coeffs.iter())
+            .all(|(x, y)| (*x as i32 - *y as i32).rem_euclid(Q as i32) == 0)
+    })
+}
+
+//Enumerates keys in decreasing probability according to BP marginals and
+//checks them against the public key
+#[pyclass]
+pub struct KeyEnumeration {
+    pk: PublicKey,
+    #[pyo3(get)]
+    threads: usize,
+    //Number of keys checked in the last call of enumerate
+    #[pyo3(get)]
+    candidates: usize,
+}
+
+#[pymethods]
+impl KeyEnumeration {
+    #[new]
+    #[args(threads = "1")]
+    pub fn new(pk: PublicKey, threads: usize) -> PyResult<Self> {
+        if threads == 0 {
+            return Err(PyValueError::new_err(
+                "Number of threads has to be positive.",
+            ));
+        }
+        Ok(KeyEnumeration {
+            pk,
+            threads,
+            candidates: 0,
+        })
+    }
+
+    //Checks at most budget keys, starting with the most likely one, and
+    //returns the first key (e, s) matching the public key
+    #[args(budget = "1000000")]
+    pub fn enumerate(
+        &mut self,
+        py: Python,
+        marginals: PyReadonlyArray2<f64>,
+        budget: usize,
+    ) -> PyResult<Option<Vec<i16>>> {
+        let mut candidates = Candidates::new(&key_marginals(marginals)?);
+        let pk = self.pk;
+        let threads = self.threads;
+        self.candidates = 0;
+        while self.candidates < budget {
+            let count = std::cmp::min(threads * BATCH, budget - self.candidates);
+            let remaining = &mut candidates;
+            let (checked, found) = py.allow_threads(move || {
+                let batch: Vec<Vec<i16>> = remaining.take(count).collect();
+                if batch.is_empty() {
+                    return (0, None);
+                }
+                let checked = batch.len();
+                let chunk_size = (batch.len() + threads - 1) / threads;
+                let handles: Vec<_> = batch
+                    .chunks(chunk_size)
+                    .enumerate()
+                    .map(|(c, chunk)| {
+                        let chunk = chunk.to_vec();
+                        thread::spawn(move || {
+                            chunk
+                                .into_iter()
+                                .enumerate()
+                                .find(|(_, key)| matches(&pk, key))
+                                .map(|(i, key)| (c * chunk_size + i, key))
+                        })
+                    })
+                    .collect();
+                //The first chunk holding a match has the most likely one
+                let found = handles
+                    .into_iter()
+                    .map(|h| h.join().unwrap())
+                    .fold(None, |found, key| found.or(key));
+                (checked, found)
+            });
+            if let Some((i, key)) = found {
+                self.candidates += i + 1;
+                return Ok(Some(key));
+            }
+            if checked == 0 {
+                break;
+            }
+            self.candidates += checked;
+            py.check_signals()?;
+        }
+        Ok(None)
+    }
+}
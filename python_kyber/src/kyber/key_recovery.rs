@@ -0,0 +1,296 @@
+use super::constants::*;
+use super::wrapper::*;
+use super::{Poly, Polyvec, PublicKey, SecretKey};
+use numpy::PyReadonlyArray2;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+//Number of coefficients of e (and of s), the key is (e, s) as in the graph
+pub const LEN: usize = K * N;
+
+//Coefficients in the normal domain reduced to 0..Q
+fn to_normal(poly: &Poly) -> [u32; N] {
+    let normal = poly.intt().montgomery_reduce();
+    let mut res = [0; N];
+    for (r, c) in res.iter_mut().zip(normal.coeffs.iter()) {
+        *r = (*c as i32).rem_euclid(Q as i32) as u32;
+    }
+    res
+}
+
+fn centered(x: u32) -> i16 {
+    if x > (Q / 2) as u32 {
+        x as i16 - Q as i16
+    } else {
+        x as i16
+    }
+}
+
+fn inverse(x: u32) -> u32 {
+    let q = Q as u32;
+    let (mut res, mut base, mut exp) = (1, x % q, q - 2);
+    while exp > 0 {
+        if exp & 1 == 1 {
+            res = res * base % q;
+        }
+        base = base * base % q;
+        exp >>= 1;
+    }
+    res
+}
+
+//Gaussian elimination mod q of an augmented matrix with at least as many
+//rows as unknowns, None if the solution is not unique or does not exist
+fn eliminate(mut mat: Vec<Vec<u32>>, unknowns: usize) -> Option<Vec<u32>> {
+    let q = Q as u32;
+    for col in 0..unknowns {
+        let pivot = (col..mat.len()).find(|r| mat[*r][col] != 0)?;
+        mat.swap(col, pivot);
+        let inv = inverse(mat[col][col]);
+        mat[col][col..].iter_mut().for_each(|x| *x = *x * inv % q);
+        let (done, rest) = mat.split_at_mut(col + 1);
+        let pivot_row = &done[col];
+        for row in rest.iter_mut().filter(|row| row[col] != 0) {
+            let f = q - row[col];
+            for (x, p) in row[col..].iter_mut().zip(pivot_row[col..].iter()) {
+                *x = (*x + f * p) % q;
+            }
+        }
+    }
+    if mat[unknowns..].iter().any(|row| row[unknowns] != 0) {
+        return None;
+    }
+    let mut res = vec![0; unknowns];
+    for col in (0..unknowns).rev() {
+        let row = &mat[col];
+        let sum = (col + 1..unknowns).fold(0, |sum, c| (sum + row[c] * res[c]) % q);
+        res[col] = (row[unknowns] + q - sum) % q;
+    }
+    Some(res)
+}
+
+//Rows of probabilities of -eta..=eta per coefficient of (e, s) as returned
+//by CheckGraph.get_marginals, further rows are ignored
+pub fn key_marginals(marginals: PyReadonlyArray2<f64>) -> PyResult<Vec<Vec<f64>>> {
+    let marginals = marginals.as_array();
+    let (rows, size) = marginals.dim();
+    if rows < 2 * LEN {
+        return Err(PyValueError::new_err(format!(
+            "Expected marginals of at least {} coefficients, got {}.",
+            2 * LEN,
+            rows
+        )));
+    }
+    if size % 2 == 0 {
+        return Err(PyValueError::new_err(
+            "Expected an odd number of probabilities per coefficient.",
+        ));
+    }
+    Ok(marginals
+        .outer_iter()
+        .take(2 * LEN)
+        .map(|row| row.to_vec())
+        .collect())
+}
+
+//Recovers the full key from BP marginals using t = A*s + e: the most
+//reliable half of the coefficients is fixed to its most likely value, the
+//others are solved for mod q and the result is checked by decapsulation
+#[pyclass]
+pub struct KeyRecovery {
+    pk: PublicKey,
+    hashpk: [u8; 32],
+    //a[i*K + j] is the entry (i, j) of A in the normal domain
+    a: Vec<[u32; N]>,
+    //t in the normal domain
+    t: Vec<u32>,
+    //Number of candidates solved for in the last call of recover
+    #[pyo3(get)]
+    candidates: usize,
+}
+
+#[pymethods]
+impl KeyRecovery {
+    #[new]
+    pub fn new(pk: PublicKey) -> Self {
+        let mut pk = pk;
+        let mut hashpk = [0; 32];
+        hash_h(&mut hashpk, &pk.to_bytes());
+        KeyRecovery {
+            pk,
+            hashpk,
+            a: pk
+                .a
+                .iter()
+                .flat_map(|row| row.vec.iter().map(|poly| to_normal(poly)))
+                .collect(),
+            t: pk
+                .pk
+                .vec
+                .iter()
+                .flat_map(|poly| to_normal(poly).to_vec())
+                .collect(),
+            candidates: 0,
+        }
+    }
+
+    //The first candidate fixes the LEN most reliable coefficients, each
+    //further one swaps one of the least reliable fixed coefficients for the
+    //next most reliable one
+    //Returns the secret key (with z set to zero) if a candidate decapsulates
+    #[args(attempts = "16")]
+    pub fn recover(
+        &mut self,
+        py: Python,
+        marginals: PyReadonlyArray2<f64>,
+        attempts: usize,
+    ) -> PyResult<Option<SecretKey>> {
+        if attempts > LEN + 1 {
+            return Err(PyValueError::new_err(format!(
+                "At most {} attempts are possible.",
+                LEN + 1
+            )));
+        }
+        let marginals = key_marginals(marginals)?;
+        let half = (marginals[0].len() / 2) as i16;
+        let mut best: Vec<(usize, i16, f64)> = marginals
+            .iter()
+            .enumerate()
+            .map(|(var, row)| {
+                let sum: f64 = row.iter().sum();
+                let (idx, p) = row
+                    .iter()
+                    .enumerate()
+                    .fold(
+                        (0, f64::NEG_INFINITY),
+                        |m, (i, p)| {
+                            if *p > m.1 {
+                                (i, *p)
+                            } else {
+                                m
+                            }
+                        },
+                    );
+                (var, idx as i16 - half, p / sum)
+            })
+            .collect();
+        best.sort_by(|b0, b1| b1.2.partial_cmp(&b0.2).unwrap_or(std::cmp::Ordering::Equal));
+        self.candidates = 0;
+        for attempt in 0..attempts {
+            let fixed: Vec<(usize, i16)> = if attempt == 0 {
+                best[..LEN].iter().map(|(var, v, _)| (*var, *v)).collect()
+            } else {
+                best[..LEN + 1]
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| *i != LEN - attempt)
+                    .map(|(_, (var, v, _))| (*var, *v))
+                    .collect()
+            };
+            self.candidates += 1;
+            let this = &*self;
+            let sk = py.allow_threads(|| this.solve(&fixed).and_then(|key| this.check(&key)));
+            if sk.is_some() {
+                return Ok(sk);
+            }
+            py.check_signals()?;
+        }
+        Ok(None)
+    }
+
+    //Checks a full key (e, s), returns the secret key if it decapsulates
+    pub fn verify(&self, key: Vec<i16>) -> PyResult<Option<SecretKey>> {
+        if key.len() < 2 * LEN {
+            return Err(PyValueError::new_err(format!(
+                "Expected at least {} coefficients.",
+                2 * LEN
+            )));
+        }
+        Ok(self.check(&key[..2 * LEN]))
+    }
+}
+
+impl KeyRecovery {
+    //Coefficient of s[col] in (A*s)[row]
+    fn coeff(&self, row: usize, col: usize) -> u32 {
+        let (i, k) = (row / N, row % N);
+        let (j, l) = (col / N, col % N);
+        let a = &self.a[i * K + j];
+        //Negacyclic, x^N = -1
+        if k >= l {
+            a[k - l]
+        } else {
+            (Q as u32 - a[N + k - l]) % Q as u32
+        }
+    }
+
+    //Solves for all coefficients of (e, s) given the values of some of them,
+    //the rows of the known coefficients of e determine the unknown ones of s
+    //None if the fixed coefficients do not determine a unique key
+    fn solve(&self, fixed: &[(usize, i16)]) -> Option<Vec<i16>> {
+        let q = Q as u32;
+        let mut values: Vec<Option<u32>> = vec![None; 2 * LEN];
+        for (var, v) in fixed.iter() {
+            values[*var] = Some((*v as i32).rem_euclid(Q as i32) as u32);
+        }
+        let (e, s) = values.split_at_mut(LEN);
+        let unknown: Vec<usize> = (0..LEN).filter(|col| s[*col].is_none()).collect();
+        let mat: Vec<Vec<u32>> = (0..LEN)
+            .filter_map(|row| e[row].map(|value| (row, value)))
+            .map(|(row, value)| {
+                let mut rhs = (self.t[row] + q - value) % q;
+                for (col, v) in s.iter().enumerate() {
+                    if let Some(v) = v {
+                        rhs = (rhs + q - self.coeff(row, col) * v % q) % q;
+                    }
+                }
+                let mut res: Vec<u32> = unknown.iter().map(|col| self.coeff(row, *col)).collect();
+                res.push(rhs);
+                res
+            })
+            .collect();
+        if mat.len() < unknown.len() {
+            return None;
+        }
+        for (col, v) in unknown.iter().zip(eliminate(mat, unknown.len())?) {
+            s[*col] = Some(v);
+        }
+        for row in (0..LEN).filter(|row| e[*row].is_none()) {
+            let prod = s.iter().enumerate().fold(0, |sum, (col, v)| {
+                (sum + self.coeff(row, col) * v.unwrap()) % q
+            });
+            e[row] = Some((self.t[row] + q - prod) % q);
+        }
+        Some(values.into_iter().map(|v| centered(v.unwrap())).collect())
+    }
+
+    //Secret key of (e, s) if all coefficients are in the support of the
+    //binomial distribution and it decapsulates a fresh encapsulation
+    fn check(&self, key: &[i16]) -> Option<SecretKey> {
+        if key.iter().any(|c| c.abs() > ETA as i16) {
+            return None;
+        }
+        let mut s = Polyvec::new();
+        for (poly, coeffs) in s.vec.iter_mut().zip(key[LEN..].chunks(N)) {
+            poly.coeffs.copy_from_slice(coeffs);
+        }
+        //z is only used to reject invalid ciphertexts and cannot be recovered
+        let mut sk = SecretKey {
+            sk: s.ntt(),
+            hashpk: self.hashpk,
+            pk: self.pk,
+            z: [0; SZ_SS],
+        };
+        let mut pk = self.pk;
+        let mut ct = [0; SZ_CT];
+        let mut ss = [0; SZ_SS];
+        let mut ss_dec = [0; SZ_SS];
+        encaps(&mut ct, &mut ss, &pk.to_bytes());
+        decaps(&mut ss_dec, &ct, &sk.to_bytes());
+        if ss == ss_dec {
+            Some(sk)
+        } else {
+            None
+        }
+    }
+}
@@ -1,29 +1,31 @@
-use crate::constants::*;
-use crate::kyber::wrapper::*;
-use crate::{Ciphertext, Poly, Polyvec, PublicKey, SecretKey};
+use super::constants::*;
+use super::wrapper::*;
+use super::{Ciphertext, Poly, Polyvec, PublicKey, SecretKey};
 use pyo3::prelude::*;
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
 
 #[pyclass]
 #[derive(Copy, Clone)]
 pub struct KyberSample {
     #[pyo3(get)]
-    pk: PublicKey, //NTT
+    pub pk: PublicKey, //NTT
     #[pyo3(get)]
-    sk: SecretKey, //NTT
+    pub sk: SecretKey, //NTT
     #[pyo3(get)]
-    ct: Ciphertext, //Normal
+    pub ct: Ciphertext, //Normal
     #[pyo3(get)]
-    ss: [u8; 32],
+    pub ss: [u8; 32],
     #[pyo3(get)]
-    nu: [u8; 32],
+    pub nu: [u8; 32],
     #[pyo3(get)]
-    e1: Polyvec, //Normal
+    pub e1: Polyvec, //Normal
     #[pyo3(get)]
-    e2: Poly, //Normal
+    pub e2: Poly, //Normal
     #[pyo3(get)]
-    r: Polyvec, //Normal
+    pub r: Polyvec, //Normal
     #[pyo3(get)]
-    e: Polyvec, //Normal
+    pub e: Polyvec, //Normal
 }
 
 #[pymethods]
@@ -133,6 +135,37 @@ impl KyberSample {
             e: e.clone(),
         }
     }
+    //Same as generate, but all randomness (keys, nu, e, e1, e2, r) is derived from seed
+    //with ChaCha20, which (unlike StdRng) is stable across rand versions and platforms
+    #[staticmethod]
+    pub fn generate_seeded(seed: u64, verify_decaps: bool) -> Self {
+        let mut rng = ChaCha20Rng::seed_from_u64(seed);
+        let mut pk: [u8; SZ_PK] = [0; SZ_PK];
+        let mut sk: [u8; SZ_SK] = [0; SZ_SK];
+        let mut e = Polyvec::new();
+        let mut key_coins: [u8; 64] = [0; 64];
+        rng.fill_bytes(&mut key_coins);
+        keygen_manipulated_derand(&mut pk, &mut sk, &mut e, &key_coins);
+        KyberSample::encaps_seeded(&mut rng, verify_decaps, pk, sk, e)
+    }
+    //Same as generate_with_key, but nu, e1, e2 and r are derived from seed
+    #[staticmethod]
+    pub fn generate_with_key_seeded(
+        seed: u64,
+        verify_decaps: bool,
+        pk_k: &mut PublicKey,
+        sk_k: &mut SecretKey,
+        e: &mut Polyvec,
+    ) -> Self {
+        let mut rng = ChaCha20Rng::seed_from_u64(seed);
+        KyberSample::encaps_seeded(
+            &mut rng,
+            verify_decaps,
+            pk_k.to_bytes(),
+            sk_k.to_bytes(),
+            *e,
+        )
+    }
     //Do we need to copy?
     pub fn is_valid_ct(&self, ct: &Ciphertext) -> bool {
         let mut ss: [u8; SZ_SS] = [0; SZ_SS];
@@ -153,3 +186,41 @@ impl KyberSample {
         self.clone()
     }
 }
+
+impl KyberSample {
+    pub fn encaps_seeded<R: RngCore>(
+        rng: &mut R,
+        verify_decaps: bool,
+        pk: [u8; SZ_PK],
+        sk: [u8; SZ_SK],
+        e: Polyvec,
+    ) -> Self {
+        let mut ct: [u8; SZ_CT] = [0; SZ_CT];
+        let mut ss: [u8; SZ_SS] = [0; SZ_SS];
+        let mut ss2: [u8; SZ_SS] = [0; SZ_SS];
+        let mut nu: [u8; 32] = [0; 32];
+        let mut e1 = Polyvec::new();
+        let mut r = Polyvec::new();
+        let mut e2 = Poly::new();
+        let mut enc_coins: [u8; 32] = [0; 32];
+        rng.fill_bytes(&mut enc_coins);
+        encaps_manipulated_derand(
+            &mut ct, &mut ss, &pk, &mut nu, &mut e1, &mut e2, &mut r, &enc_coins,
+        );
+        if verify_decaps {
+            decaps(&mut ss2, &ct, &sk);
+            assert_eq!(ss, ss2);
+        }
+        KyberSample {
+            ss: ss,
+            pk: PublicKey::from_bytes(&pk),
+            sk: SecretKey::from_bytes(&sk),
+            ct: Ciphertext::from_bytes(&ct),
+            nu: nu,
+            e1: e1,
+            e2: e2,
+            r: r,
+            e: e,
+        }
+    }
+}
@@ -0,0 +1,10 @@
+pub const SZ_PK: usize = 1568;
+pub const SZ_SK: usize = 3168;
+pub const SZ_CT: usize = 1568;
+
+pub const POLYVEC_COMPRESSED_BYTES: usize = K * 352;
+pub const POLY_COMPRESSED_BYTES: usize = 160;
+
+pub const K: usize = 4;
+pub const DV: usize = 5;
+pub const ETA: usize = 2;
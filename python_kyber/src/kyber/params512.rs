@@ -0,0 +1,10 @@
+pub const SZ_PK: usize = 800;
+pub const SZ_SK: usize = 1632;
+pub const SZ_CT: usize = 768;
+
+pub const POLYVEC_COMPRESSED_BYTES: usize = K * 320;
+pub const POLY_COMPRESSED_BYTES: usize = 128;
+
+pub const K: usize = 2;
+pub const DV: usize = 3;
+pub const ETA: usize = 3;
@@ -0,0 +1,10 @@
+pub const SZ_PK: usize = 1184;
+pub const SZ_SK: usize = 2400;
+pub const SZ_CT: usize = 1088;
+
+pub const POLYVEC_COMPRESSED_BYTES: usize = K * 320;
+pub const POLY_COMPRESSED_BYTES: usize = 128;
+
+pub const K: usize = 3;
+pub const DV: usize = 4;
+pub const ETA: usize = 2;
@@ -1,5 +1,5 @@
-use crate::constants::*;
-use crate::wrapper::*;
+use super::constants::*;
+use super::wrapper::*;
 use pyo3::class::number::PyNumberProtocol;
 use pyo3::exceptions::PyValueError;
 use pyo3::prelude::*;
@@ -8,7 +8,7 @@ use pyo3::prelude::*;
 #[derive(Copy, Clone)]
 #[repr(C)]
 pub struct Poly {
-    coeffs: [i16; N],
+    pub coeffs: [i16; N],
 }
 
 #[pymethods]
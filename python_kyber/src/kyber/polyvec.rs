@@ -2,9 +2,9 @@ use pyo3::class::number::PyNumberProtocol;
 use pyo3::exceptions::PyValueError;
 use pyo3::prelude::*;
 
-use crate::constants::*;
-use crate::wrapper::*;
-use crate::Poly;
+use super::constants::*;
+use super::wrapper::*;
+use super::Poly;
 
 use itertools::izip;
 
@@ -1,6 +1,6 @@
-use crate::constants::*;
-use crate::wrapper::*;
-use crate::Polyvec;
+use super::constants::*;
+use super::wrapper::*;
+use super::Polyvec;
 use pyo3::prelude::*;
 
 #[pyclass]
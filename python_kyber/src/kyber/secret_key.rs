@@ -1,6 +1,6 @@
-use crate::constants::*;
-use crate::wrapper::*;
-use crate::{Polyvec, PublicKey};
+use super::constants::*;
+use super::wrapper::*;
+use super::{Polyvec, PublicKey};
 use pyo3::exceptions::PyValueError;
 use pyo3::prelude::*;
 
@@ -1,7 +1,7 @@
-use crate::constants::*;
-use crate::wrapper::*;
+use super::constants::*;
+use super::wrapper::*;
 #[allow(unused_imports)]
-use crate::*;
+use super::*;
 #[test]
 fn test_kyber_sample() {
     let _ = KyberSample::generate(true);
@@ -42,3 +42,37 @@ fn test_kyber() {
     assert_eq!(ss2, ss3);
     assert_eq!(ss4, ss5);
 }
+#[test]
+fn test_kyber_derand() {
+    let coins: [u8; 64] = [7; 64];
+    let mut pk: [[u8; SZ_PK]; 2] = [[0; SZ_PK]; 2];
+    let mut sk: [[u8; SZ_SK]; 2] = [[0; SZ_SK]; 2];
+    let mut ct: [[u8; SZ_CT]; 2] = [[0; SZ_CT]; 2];
+    let mut ss: [[u8; SZ_SS]; 2] = [[0; SZ_SS]; 2];
+    let mut ss2: [u8; SZ_SS] = [0; SZ_SS];
+    let mut nu: [[u8; 32]; 2] = [[0; 32]; 2];
+    let mut e = [Polyvec::new(); 2];
+    let mut r = [Polyvec::new(); 2];
+    let mut e1 = [Polyvec::new(); 2];
+    let mut e2 = [Poly::new(); 2];
+    for i in 0..2 {
+        keygen_manipulated_derand(&mut pk[i], &mut sk[i], &mut e[i], &coins);
+        encaps_manipulated_derand(
+            &mut ct[i],
+            &mut ss[i],
+            &pk[i],
+            &mut nu[i],
+            &mut e1[i],
+            &mut e2[i],
+            &mut r[i],
+            &coins[0..32],
+        );
+    }
+    decaps(&mut ss2, &ct[0], &sk[0]);
+    assert_eq!(&sk[0].to_vec(), &sk[1].to_vec());
+    assert_eq!(&ct[0].to_vec(), &ct[1].to_vec());
+    assert_eq!(e[0].to_lists(), e[1].to_lists());
+    assert_eq!(r[0].to_lists(), r[1].to_lists());
+    assert_eq!(ss[0], ss2);
+    let _ = KyberSample::generate_seeded(42, true);
+}
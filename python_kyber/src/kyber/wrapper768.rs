@@ -1,5 +1,5 @@
-use crate::constants::*;
-use crate::{Poly, Polyvec};
+use super::constants::*;
+use super::{Poly, Polyvec};
 use libc::c_int;
 
 #[link(name = "kyber768_clean")]
@@ -10,6 +10,12 @@ extern "C" {
         sk: *mut u8,
         e: *mut Polyvec,
     ) -> c_int;
+    fn PQCLEAN_KYBER768_CLEAN_crypto_kem_keypair_manip_derand(
+        pk: *mut u8,
+        sk: *mut u8,
+        e: *mut Polyvec,
+        coins: *const u8,
+    ) -> c_int;
     fn PQCLEAN_KYBER768_CLEAN_crypto_kem_enc(ct: *mut u8, ss: *mut u8, pk: *const u8) -> c_int;
     fn PQCLEAN_KYBER768_CLEAN_crypto_kem_dec(ss: *mut u8, ct: *const u8, sk: *const u8) -> c_int;
     fn PQCLEAN_KYBER768_CLEAN_crypto_kem_enc_manip(
@@ -21,6 +27,16 @@ extern "C" {
         e1: *mut Polyvec,
         e2: *mut Poly,
     ) -> c_int;
+    fn PQCLEAN_KYBER768_CLEAN_crypto_kem_enc_manip_derand(
+        ct: *mut u8,
+        ss: *mut u8,
+        pk: *const u8,
+        nu: *mut u8,
+        r: *mut Polyvec,
+        e1: *mut Polyvec,
+        e2: *mut Poly,
+        coins: *const u8,
+    ) -> c_int;
     fn PQCLEAN_KYBER768_CLEAN_crypto_kem_dec_glitch(
         ss: *mut u8,
         ct: *const u8,
@@ -39,8 +55,8 @@ extern "C" {
     fn PQCLEAN_KYBER768_CLEAN_polyvec_decompress(r: *mut Polyvec, a: *const u8);
     fn PQCLEAN_KYBER768_CLEAN_polyvec_frombytes(r: *mut Polyvec, a: *const u8);
     fn PQCLEAN_KYBER768_CLEAN_polyvec_tobytes(r: *mut u8, a: *const Polyvec);
-    fn unpack_pk(pk: *mut Polyvec, seed: *mut u8, packedpk: *const u8);
-    fn pack_pk(r: *mut u8, pk: *mut Polyvec, seed: *const u8);
+    fn PQCLEAN_KYBER768_CLEAN_unpack_pk(pk: *mut Polyvec, seed: *mut u8, packedpk: *const u8);
+    fn PQCLEAN_KYBER768_CLEAN_pack_pk(r: *mut u8, pk: *mut Polyvec, seed: *const u8);
     fn PQCLEAN_KYBER768_CLEAN_gen_matrix(a: *mut Polyvec, seed: *const u8, transposed: c_int);
     fn PQCLEAN_KYBER768_CLEAN_poly_reduce(r: *mut Poly);
     fn PQCLEAN_KYBER768_CLEAN_poly_basemul_montgomery(
@@ -62,12 +78,19 @@ extern "C" {
     fn PQCLEAN_KYBER768_CLEAN_poly_tomsg(msg: *mut u8, a: *const Poly);
     fn PQCLEAN_KYBER768_CLEAN_polyvec_add(r: *mut Polyvec, a: *const Polyvec, b: *const Polyvec);
     fn PQCLEAN_KYBER768_CLEAN_montgomery_reduce(x: i32) -> i16;
+    //From PQClean/common/fips202.c, linked into the library
+    fn sha3_256(output: *mut u8, input: *const u8, inlen: usize);
 }
 
 pub fn montgomery_reduce(x: i32) -> i16 {
     unsafe { PQCLEAN_KYBER768_CLEAN_montgomery_reduce(x) }
 }
 
+//SHA3-256, used as H by Kyber (e.g. for the hash of the public key)
+pub fn hash_h(out: &mut [u8; 32], input: &[u8]) {
+    unsafe { sha3_256(out.as_mut_ptr(), input.as_ptr(), input.len()) }
+}
+
 pub fn poly_tomsg(msg: &mut [u8], a: &Poly) {
     unsafe {
         PQCLEAN_KYBER768_CLEAN_poly_tomsg(msg.as_mut_ptr(), a);
@@ -147,12 +170,12 @@ pub fn sf_gen_matrix(a: &mut [Polyvec; K], seed: &[u8], transposed: bool) {
 }
 pub fn sf_unpack_pk(pk: &mut Polyvec, seed: &mut [u8], packedpk: &[u8]) {
     unsafe {
-        unpack_pk(pk, seed.as_mut_ptr(), packedpk.as_ptr());
+        PQCLEAN_KYBER768_CLEAN_unpack_pk(pk, seed.as_mut_ptr(), packedpk.as_ptr());
     }
 }
 pub fn sf_pack_pk(r: &mut [u8], pk: &mut Polyvec, seed: &[u8]) {
     unsafe {
-        pack_pk(r.as_mut_ptr(), pk, seed.as_ptr());
+        PQCLEAN_KYBER768_CLEAN_pack_pk(r.as_mut_ptr(), pk, seed.as_ptr());
     }
 }
 pub fn polyvec_compress(r: &mut [u8], a: &mut Polyvec) {
@@ -226,6 +249,22 @@ pub fn keygen_manipulated(pk: &mut [u8], sk: &mut [u8], e: &mut Polyvec) {
     }
 }
 
+//coins: 2*32 bytes, the first half seeds the key pair, the second half is z
+pub fn keygen_manipulated_derand(pk: &mut [u8], sk: &mut [u8], e: &mut Polyvec, coins: &[u8]) {
+    assert_eq!(coins.len(), 64);
+    unsafe {
+        assert_eq!(
+            PQCLEAN_KYBER768_CLEAN_crypto_kem_keypair_manip_derand(
+                pk.as_mut_ptr(),
+                sk.as_mut_ptr(),
+                e,
+                coins.as_ptr()
+            ),
+            0
+        );
+    }
+}
+
 pub fn encaps(ct: &mut [u8], ss: &mut [u8], pk: &[u8]) {
     unsafe {
         assert_eq!(
@@ -260,6 +299,35 @@ pub fn encaps_manipulated(
     }
 }
 
+//coins: 32 bytes from which the message nu is derived
+pub fn encaps_manipulated_derand(
+    ct: &mut [u8],
+    ss: &mut [u8],
+    pk: &[u8],
+    nu: &mut [u8],
+    e1: &mut Polyvec,
+    e2: &mut Poly,
+    r: &mut Polyvec,
+    coins: &[u8],
+) {
+    assert_eq!(coins.len(), 32);
+    unsafe {
+        assert_eq!(
+            PQCLEAN_KYBER768_CLEAN_crypto_kem_enc_manip_derand(
+                ct.as_mut_ptr(),
+                ss.as_mut_ptr(),
+                pk.as_ptr(),
+                nu.as_mut_ptr(),
+                r,
+                e1,
+                e2,
+                coins.as_ptr()
+            ),
+            0
+        );
+    }
+}
+
 pub fn decaps(ss: &mut [u8], ct: &[u8], sk: &[u8]) {
     unsafe {
         assert_eq!(
@@ -0,0 +1,38 @@
+#[path = "kyber/params1024.rs"]
+pub mod params;
+#[path = "kyber/wrapper1024.rs"]
+pub mod wrapper;
+
+#[path = "kyber/ciphertext.rs"]
+pub mod ciphertext;
+#[path = "kyber/constants.rs"]
+pub mod constants;
+#[path = "kyber/inequality_generator.rs"]
+pub mod inequality_generator;
+#[path = "kyber/key_enumeration.rs"]
+pub mod key_enumeration;
+#[path = "kyber/key_recovery.rs"]
+pub mod key_recovery;
+#[path = "kyber/kyber_sample.rs"]
+pub mod kyber_sample;
+#[path = "kyber/poly.rs"]
+pub mod poly;
+#[path = "kyber/polyvec.rs"]
+pub mod polyvec;
+#[path = "kyber/public_key.rs"]
+pub mod public_key;
+#[path = "kyber/secret_key.rs"]
+pub mod secret_key;
+#[cfg(test)]
+#[path = "kyber/test.rs"]
+mod test;
+
+pub use self::ciphertext::Ciphertext;
+pub use self::inequality_generator::InequalityGenerator;
+pub use self::key_enumeration::KeyEnumeration;
+pub use self::key_recovery::KeyRecovery;
+pub use self::kyber_sample::KyberSample;
+pub use self::poly::Poly;
+pub use self::polyvec::Polyvec;
+pub use self::public_key::PublicKey;
+pub use self::secret_key::SecretKey;
@@ -1,17 +1,34 @@
 use pyo3::prelude::*;
 
 #[allow(unused)]
-pub mod kyber;
-pub use kyber::*;
+pub mod kyber1024;
+#[allow(unused)]
+pub mod kyber512;
+#[allow(unused)]
+pub mod kyber768;
+
+//Adds the classes of one parameter set as submodule (e.g. python_kyber.Kyber512)
+macro_rules! add_parameter_set {
+    ($py:expr, $m:expr, $set:ident, $name:literal) => {{
+        let sub = PyModule::new($py, $name)?;
+        sub.add_class::<$set::Ciphertext>()?;
+        sub.add_class::<$set::SecretKey>()?;
+        sub.add_class::<$set::PublicKey>()?;
+        sub.add_class::<$set::KyberSample>()?;
+        sub.add_class::<$set::InequalityGenerator>()?;
+        sub.add_class::<$set::KeyRecovery>()?;
+        sub.add_class::<$set::KeyEnumeration>()?;
+        sub.add_class::<$set::Poly>()?;
+        sub.add_class::<$set::Polyvec>()?;
+        sub.add_class::<$set::constants::KyberConstants>()?;
+        $m.add_submodule(sub)?;
+    }};
+}
 
 #[pymodule]
-fn python_kyber(_py: Python, m: &PyModule) -> PyResult<()> {
-    m.add_class::<Ciphertext>()?;
-    m.add_class::<SecretKey>()?;
-    m.add_class::<PublicKey>()?;
-    m.add_class::<KyberSample>()?;
-    m.add_class::<Poly>()?;
-    m.add_class::<Polyvec>()?;
-    m.add_class::<kyber::constants::KyberConstants>()?;
+fn python_kyber(py: Python, m: &PyModule) -> PyResult<()> {
+    add_parameter_set!(py, m, kyber512, "Kyber512");
+    add_parameter_set!(py, m, kyber768, "Kyber768");
+    add_parameter_set!(py, m, kyber1024, "Kyber1024");
     Ok(())
 }